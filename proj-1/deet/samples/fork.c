@@ -0,0 +1,39 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+int compute(int n) {
+    return n * 2;
+}
+
+int main() {
+    int fds[2];
+    if (pipe(fds) < 0) {
+        return 1;
+    }
+    pid_t producer = fork();
+    if (producer == 0) {
+        /* The first child runs another program, with its output going into the pipe */
+        dup2(fds[1], STDOUT_FILENO);
+        close(fds[0]);
+        close(fds[1]);
+        execl("/bin/echo", "echo", "hello from echo", NULL);
+        exit(1);
+    }
+    pid_t worker = fork();
+    if (worker == 0) {
+        close(fds[1]);
+        char buf[64];
+        ssize_t len = read(fds[0], buf, sizeof(buf) - 1);
+        buf[len > 0 ? len : 0] = '\0';
+        printf("worker %d read: %s", compute(21), buf);
+        exit(0);
+    }
+    close(fds[0]);
+    close(fds[1]);
+    waitpid(producer, NULL, 0);
+    waitpid(worker, NULL, 0);
+    printf("parent computed %d\n", compute(5));
+    return 0;
+}
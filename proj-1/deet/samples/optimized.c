@@ -0,0 +1,30 @@
+#include <stdio.h>
+
+struct point {
+    int x;
+    int y;
+};
+
+/* noinline keeps each function a real frame, so its variables can be inspected */
+__attribute__((noinline)) long sum_squares(int n) {
+    long total = 0;
+    for (int i = 1; i <= n; i++) {
+        total += (long)i * i;
+    }
+    return total;
+}
+
+__attribute__((noinline)) int manhattan(struct point p) {
+    int dx = p.x < 0 ? -p.x : p.x;
+    int dy = p.y < 0 ? -p.y : p.y;
+    return dx + dy;
+}
+
+int main(int argc, char *argv[]) {
+    int unused = argc * 7;
+    struct point p = {argc - 4, argc + 2};
+    long squares = sum_squares(argc + 9);
+    int distance = manhattan(p);
+    printf("%ld %d\n", squares, distance);
+    return 0;
+}
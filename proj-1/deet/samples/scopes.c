@@ -0,0 +1,28 @@
+#include <stdio.h>
+
+/* always_inline is honored even without optimization, so this is inlined into its callers */
+static inline __attribute__((always_inline)) int clamp(int value, int limit) {
+    int result = value;
+    if (result > limit) {
+        result = limit;
+    }
+    return result;
+}
+
+int total(int count) {
+    int sum = 0;
+    for (int i = 0; i < count; i++) {
+        int square = i * i;
+        sum += clamp(square, 10);
+    }
+    {
+        int sum = -1;
+        printf("shadowed sum: %d\n", sum);
+    }
+    return sum;
+}
+
+int main() {
+    printf("total: %d\n", total(5));
+    return 0;
+}
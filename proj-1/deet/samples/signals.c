@@ -0,0 +1,19 @@
+#include <signal.h>
+#include <stdio.h>
+#include <unistd.h>
+
+volatile sig_atomic_t received = 0;
+
+void on_usr1(int signum) {
+    received++;
+}
+
+int main() {
+    signal(SIGUSR1, on_usr1);
+    for (int i = 0; i < 3; i++) {
+        raise(SIGUSR1);
+    }
+    alarm(1);
+    printf("received %d SIGUSR1\n", received);
+    return 0;
+}
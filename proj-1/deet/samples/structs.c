@@ -0,0 +1,52 @@
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef enum { RED, GREEN, BLUE } color_t;
+
+struct node {
+    int value;
+    struct node *next;
+};
+
+struct point {
+    int x;
+    int y;
+};
+
+typedef struct {
+    const char *name;
+    struct point corners[2];
+    color_t color;
+    union {
+        int as_int;
+        float as_float;
+    } tag;
+} shape_t;
+
+int grid[2][3] = {{1, 2, 3}, {4, 5, 6}};
+
+struct node *push(struct node *head, int value) {
+    struct node *node = malloc(sizeof(struct node));
+    node->value = value;
+    node->next = head;
+    return node;
+}
+
+int sum(struct node *list) {
+    int total = 0;
+    for (struct node *p = list; p != NULL; p = p->next) {
+        total += p->value;
+    }
+    return total;
+}
+
+int main() {
+    struct node *list = NULL;
+    for (int i = 1; i <= 3; i++) {
+        list = push(list, i * 10);
+    }
+    shape_t shape = {"box", {{0, 0}, {4, 3}}, BLUE, {.as_int = 7}};
+    char label[8] = "shape";
+    printf("%s %s: %d\n", label, shape.name, sum(list));
+    return 0;
+}
@@ -0,0 +1,34 @@
+#include <pthread.h>
+#include <stdio.h>
+
+#define NUM_WORKERS 3
+
+int totals[NUM_WORKERS];
+
+int square(int n) {
+    return n * n;
+}
+
+void *worker(void *arg) {
+    int index = *(int *)arg;
+    for (int i = 1; i <= 3; i++) {
+        totals[index] += square(i + index);
+    }
+    return NULL;
+}
+
+int main() {
+    pthread_t threads[NUM_WORKERS];
+    int indices[NUM_WORKERS];
+    for (int i = 0; i < NUM_WORKERS; i++) {
+        indices[i] = i;
+        pthread_create(&threads[i], NULL, worker, &indices[i]);
+    }
+    for (int i = 0; i < NUM_WORKERS; i++) {
+        pthread_join(threads[i], NULL);
+    }
+    for (int i = 0; i < NUM_WORKERS; i++) {
+        printf("worker %d total: %d\n", i, totals[i]);
+    }
+    return 0;
+}
@@ -0,0 +1,268 @@
+use crate::dwarf_data::{DwarfData, Line, Type};
+use crate::expression::{Context, Expression};
+use crate::inferior::Inferior;
+use std::fmt;
+
+/// A user breakpoint. The original instruction byte lives in `Debugger::break_list`, which only
+/// contains addresses that currently have an enabled breakpoint.
+pub struct Breakpoint {
+    /// Stable number used to refer to the breakpoint in commands
+    pub id: usize,
+    pub addr: usize,
+    pub function: Option<String>,
+    pub line: Option<Line>,
+    pub enabled: bool,
+    /// Number of times the inferior has reached this breakpoint with its condition holding
+    pub hit_count: usize,
+    pub condition: Option<Condition>,
+    /// Number of upcoming hits to pass over without stopping
+    pub ignore_count: usize,
+    /// Debugger commands run every time the breakpoint stops the inferior
+    pub commands: Vec<String>,
+}
+
+impl Breakpoint {
+    pub fn new(
+        id: usize,
+        addr: usize,
+        function: Option<String>,
+        line: Option<Line>,
+        condition: Option<Condition>,
+    ) -> Self {
+        Breakpoint {
+            id,
+            addr,
+            function,
+            line,
+            enabled: true,
+            hit_count: 0,
+            condition,
+            ignore_count: 0,
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    /// Describes where the breakpoint is, e.g. "func2 at samples/function_calls.c:9".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.function, &self.line) {
+            (Some(func), Some(line)) => write!(f, "{} at {}", func, line),
+            (None, Some(line)) => write!(f, "{}", line),
+            (Some(func), None) => write!(f, "{}", func),
+            (None, None) => write!(f, "{:#x}", self.addr),
+        }
+    }
+}
+
+/// Called when the inferior traps on `addr`. Updates the hit and ignore counts of the enabled
+/// breakpoints there, and returns the ids of those that should stop the inferior.
+pub fn check_hits(
+    breakpoints: &mut [Breakpoint],
+    addr: usize,
+    inferior: &Inferior,
+    debug_data: &DwarfData,
+) -> Vec<usize> {
+    let mut hits = Vec::new();
+    for bp in breakpoints.iter_mut().filter(|bp| bp.enabled && bp.addr == addr) {
+        if let Some(condition) = &bp.condition {
+            match condition.evaluate(inferior, debug_data) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                // Like gdb, a condition that can't be evaluated counts as true
+                Err(err) => {
+                    println!("Error in testing condition for breakpoint {}: {}", bp.id, err)
+                }
+            }
+        }
+        bp.hit_count += 1;
+        if bp.ignore_count > 0 {
+            bp.ignore_count -= 1;
+            continue;
+        }
+        hits.push(bp.id);
+    }
+    hits
+}
+
+/// A hardware watchpoint, which stops the inferior when the watched value changes.
+pub struct Watchpoint {
+    /// Shares its numbering with breakpoints
+    pub id: usize,
+    /// What the user asked to watch, e.g. "global" or "*0x404020"
+    pub expression: String,
+    pub addr: usize,
+    pub entity_type: Type,
+    pub enabled: bool,
+    pub hit_count: usize,
+    /// Debug register the watchpoint is programmed into, while it is armed
+    pub slot: Option<usize>,
+    /// Last value seen, or empty if the memory could not be read
+    pub value: Vec<u8>,
+    /// Debugger commands run every time the watchpoint stops the inferior
+    pub commands: Vec<String>,
+}
+
+impl Watchpoint {
+    pub fn new(id: usize, expression: String, addr: usize, entity_type: Type) -> Self {
+        Watchpoint {
+            id,
+            expression,
+            addr,
+            entity_type,
+            enabled: true,
+            hit_count: 0,
+            slot: None,
+            value: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    /// Re-reads the watched memory. Returns the previous value if it changed.
+    pub fn update_value(&mut self, inferior: &Inferior) -> Option<Vec<u8>> {
+        let value = inferior
+            .read_bytes(self.addr, self.entity_type.size)
+            .unwrap_or_default();
+        if value == self.value {
+            None
+        } else {
+            Some(std::mem::replace(&mut self.value, value))
+        }
+    }
+
+    pub fn format_value(value: &[u8], entity_type: &Type, debug_data: &DwarfData, inferior: &Inferior) -> String {
+        if value.is_empty() {
+            "<unreadable>".to_string()
+        } else {
+            debug_data.format_value(entity_type, value, &|addr, len| inferior.read_bytes(addr, len).ok())
+        }
+    }
+}
+
+/// Called when the inferior trips the watchpoints in the given debug register `slots`. Returns
+/// the ids and previous values of the watchpoints whose value actually changed; writes that
+/// store the same value again don't stop the inferior.
+pub fn check_watch_hits(
+    watchpoints: &mut [Watchpoint],
+    slots: &[usize],
+    inferior: &Inferior,
+) -> Vec<(usize, Vec<u8>)> {
+    let mut hits = Vec::new();
+    for wp in watchpoints.iter_mut() {
+        if !wp.slot.is_some_and(|slot| slots.contains(&slot)) {
+            continue;
+        }
+        if let Some(old_value) = wp.update_value(inferior) {
+            wp.hit_count += 1;
+            hits.push((wp.id, old_value));
+        }
+    }
+    hits
+}
+
+/// A breakpoint condition such as `i == 5` or `p->next != 0`: any expression, which holds when
+/// it evaluates to a nonzero value.
+#[derive(Clone)]
+pub struct Condition {
+    expression: Expression,
+}
+
+impl Condition {
+    pub fn parse(text: &str, debug_data: &DwarfData) -> Result<Condition, String> {
+        Ok(Condition {
+            expression: Expression::parse(text, debug_data)?,
+        })
+    }
+
+    /// Evaluates the condition in the inferior's current frame.
+    pub fn evaluate(&self, inferior: &Inferior, debug_data: &DwarfData) -> Result<bool, String> {
+        let frame = inferior.current_frame().map_err(|err| err.to_string())?;
+        self.expression.is_true(&Context {
+            debug_data,
+            inferior: Some(inferior),
+            frame: Some(&frame),
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::inferior::Status;
+    use nix::sys::signal::Signal;
+    use std::collections::HashMap;
+
+    fn breakpoint(addr: usize, condition: Option<&str>, debug_data: &DwarfData) -> Breakpoint {
+        let condition = condition.map(|text| Condition::parse(text, debug_data).unwrap());
+        Breakpoint::new(1, addr, None, None, condition)
+    }
+
+    /// Every case shares one inferior, since tests running in parallel would reap each other's
+    /// processes.
+    #[test]
+    fn test_check_hits() {
+        let debug_data = DwarfData::from_file("samples/structs")
+            .expect("Could not read samples/structs. Have you run make?");
+        let push = debug_data.get_addr_for_function(None, "push").unwrap();
+        let func = debug_data.get_function_for_addr(push).unwrap();
+        let addr = debug_data.get_addr_after_prologue(func);
+        let mut break_list = HashMap::from([(addr, 0)]);
+        let mut inferior = Inferior::new("samples/structs", &[], &mut break_list).unwrap();
+        match inferior.continue_run(&break_list, &mut |_, _| true).unwrap() {
+            Status::Stopped(Signal::SIGTRAP, rip) => assert_eq!(rip, addr),
+            _ => panic!("samples/structs didn't stop in push"),
+        }
+        // The first call is push(NULL, 10)
+        let check = |bp: &mut Breakpoint| {
+            check_hits(std::slice::from_mut(bp), addr, &inferior, &debug_data) == vec![1]
+        };
+
+        let mut bp = breakpoint(addr, None, &debug_data);
+        assert!(check(&mut bp));
+        assert_eq!(bp.hit_count, 1);
+
+        let mut bp = breakpoint(addr, Some("value == 10"), &debug_data);
+        assert!(check(&mut bp));
+        assert_eq!(bp.hit_count, 1);
+
+        // A false condition is not a hit at all
+        let mut bp = breakpoint(addr, Some("value == 20"), &debug_data);
+        assert!(!check(&mut bp));
+        assert_eq!(bp.hit_count, 0);
+
+        // Ignored crossings still count as hits
+        let mut bp = breakpoint(addr, Some("value == 10"), &debug_data);
+        bp.ignore_count = 1;
+        assert!(!check(&mut bp));
+        assert_eq!((bp.hit_count, bp.ignore_count), (1, 0));
+        assert!(check(&mut bp));
+        assert_eq!(bp.hit_count, 2);
+
+        // A condition that fails to evaluate is taken as true
+        let mut bp = breakpoint(addr, Some("value / 0"), &debug_data);
+        assert!(check(&mut bp));
+        assert_eq!(bp.hit_count, 1);
+        let mut bp = breakpoint(addr, Some("value / 0"), &debug_data);
+        bp.ignore_count = 1;
+        assert!(!check(&mut bp));
+        assert_eq!((bp.hit_count, bp.ignore_count), (1, 0));
+
+        let mut bp = breakpoint(addr, None, &debug_data);
+        bp.enabled = false;
+        assert!(!check(&mut bp));
+        assert_eq!(bp.hit_count, 0);
+
+        // Breakpoints elsewhere are left alone
+        let mut bp = breakpoint(addr + 1, None, &debug_data);
+        assert!(!check(&mut bp));
+        assert_eq!(bp.hit_count, 0);
+
+        inferior.kill().unwrap();
+    }
+}
@@ -1,198 +1,2071 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use crate::breakpoint::{self, Breakpoint, Condition, Watchpoint};
 use crate::debugger_command::DebuggerCommand;
-use crate::inferior::Inferior;
+use crate::inferior::{ForkPolicy, Inferior, ProcessEvent};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
-use crate::inferior::Status;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
+use crate::inferior::{register_field, Status, Trap, MAX_FRAMES, NUM_WATCHPOINT_SLOTS, REGISTER_NAMES};
+use crate::disassemble::{self, Instruction, MAX_INSTRUCTION_LEN};
+use crate::examine::{quote_string, read_c_string, unit_value, ExamineFormat};
+use crate::unwind::Frame;
+use crate::signals::{describe_signal, is_crash_signal, parse_signal, SignalTable};
+use crate::dwarf_data::{DwarfData, Line, Type, TypeKind};
+use crate::error::DebuggerError;
+use crate::expression::{Context, Expression, Value};
 use rustyline::history::FileHistory;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::fs;
+use std::io;
+use std::mem::size_of;
 
 pub struct Debugger {
     target: String,
     history_path: String,
     readline: Editor<(), FileHistory>,
+    /// Lines from command scripts that are still to be run, before reading from the terminal
+    script_lines: VecDeque<String>,
+    /// Commands of the breakpoints that last stopped the inferior that are still to be run. They
+    /// come before script lines, and resuming the inferior drops the rest of them.
+    breakpoint_commands: VecDeque<String>,
+    /// Whether deet quits once the scripts are done, instead of prompting for commands
+    batch: bool,
+    /// The last signal that crashed an inferior, which decides deet's exit status
+    crash: Option<Signal>,
+    /// The current inferior, which commands act on
     inferior: Option<Inferior>,
+    /// Number of the current inferior, as shown by `info inferiors`
+    inferior_id: usize,
+    /// Processes deet follows besides the current one, created by forks. They stay stopped until
+    /// they are switched to with `inferior N`.
+    other_inferiors: Vec<(usize, Inferior)>,
+    next_inferior_id: usize,
+    fork_policy: ForkPolicy,
+    signals: SignalTable,
+    /// Number of the thread the last stop was reported in, to tell when another thread stops
+    reported_thread: usize,
     debug_data: DwarfData,
+    /// Original bytes at every address that has an enabled breakpoint
     break_list: HashMap<usize, u8>,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_breakpoint_id: usize,
+    /// Source file and last line printed by `list`, so that another `list` continues from there
+    last_listed: Option<(String, usize)>,
+    /// Level of the frame whose variables `print` shows, counting from the innermost frame
+    selected_frame: usize,
+    /// Format used by the last `x` command, and the address after the memory it showed
+    examine_format: ExamineFormat,
+    next_examine_addr: Option<usize>,
+    /// Expressions printed every time the inferior stops
+    displays: Vec<AutoDisplay>,
+    next_display_id: usize,
+}
+
+/// Most instructions `x/i` reads memory for at once
+const INSTRUCTIONS_PER_READ: usize = 64;
+
+/// Watchpoints that stopped the inferior, with their previous values
+type WatchHits = Vec<(usize, Vec<u8>)>;
+
+/// An expression added with `display`.
+struct AutoDisplay {
+    id: usize,
+    expression: Expression,
 }
 
 impl Debugger {
-    /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
-        // initialize the DwarfData
-        let debug_data = match DwarfData::from_file(target) {
-            Ok(val) => val,
-            Err(DwarfError::ErrorOpeningFile) => {
-                println!("Could not open file {}", target);
-                std::process::exit(1);
-            }
-            Err(DwarfError::DwarfFormatError(err)) => {
-                println!("Could not debugging symbols from {}:{:?}", target, err);
-                std::process::exit(1);
-            }
-        };
+    /// Initializes the debugger, reading the debugging symbols of `target`.
+    pub fn new(target: &str) -> Result<Debugger, DebuggerError> {
+        let debug_data = DwarfData::from_file(target)?;
 
         debug_data.print();
 
-        let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
-        let mut readline = Editor::<(), FileHistory>::new().expect("Create fail");
+        // Without a home directory, keep the history in the current one
+        let home = std::env::var("HOME").unwrap_or_else(|_| String::from("."));
+        let history_path = format!("{}/.deet_history", home);
+        let mut readline = Editor::<(), FileHistory>::new()?;
         // Attempt to load history from ~/.deet_history if it exists
-        let _ = readline.readline(&history_path);
+        let _ = readline.load_history(&history_path);
 
-        Debugger {
+        Ok(Debugger {
             target: target.to_string(),
             history_path,
             readline,
+            script_lines: VecDeque::new(),
+            breakpoint_commands: VecDeque::new(),
+            batch: false,
+            crash: None,
             inferior: None,
-            debug_data: debug_data,
+            inferior_id: 1,
+            other_inferiors: Vec::new(),
+            next_inferior_id: 2,
+            fork_policy: ForkPolicy::default(),
+            signals: SignalTable::default(),
+            reported_thread: 1,
+            debug_data,
             break_list: HashMap::new(),
-        }
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_breakpoint_id: 1,
+            last_listed: None,
+            selected_frame: 0,
+            examine_format: ExamineFormat::default(),
+            next_examine_addr: None,
+            displays: Vec::new(),
+            next_display_id: 1,
+        })
     }
 
-    pub fn run(&mut self) {
+    /// Makes deet quit once the queued scripts are done.
+    pub fn set_batch(&mut self, batch: bool) {
+        self.batch = batch;
+    }
+
+    /// Queues the commands in the script at `path`, to be run after any scripts queued before.
+    pub fn queue_script(&mut self, path: &str) -> io::Result<()> {
+        let lines = read_script(path)?;
+        self.script_lines.extend(lines);
+        Ok(())
+    }
+
+    /// Runs commands until `quit`, and returns deet's exit status: 0, or 128 plus the signal
+    /// number if an inferior crashed, like a shell reports a process killed by a signal.
+    pub fn run(&mut self) -> i32 {
         loop {
-            match self.get_next_command() {
+            let cmd = self.get_next_command();
+            match cmd {
                 DebuggerCommand::Run(args) => {
-                    if self.inferior.is_some() {
+                    if let Some(child) = self.inferior.as_mut() {
                         println!("Child is already processing!");
-                        let child = self.inferior.as_mut().unwrap();
                         println!("Killing running inferior (pid {})", child.pid());
-                        child.kill();
+                        if let Err(err) = child.kill() {
+                            println!("Error killing inferior: {}", err);
+                        }
                         self.inferior = None;
                     }
-                    if let Some(inferior) = Inferior::new(&self.target, &args, &mut self.break_list) {
-                        // Create the inferior
-                        self.inferior = Some(inferior);
-                        
-                        // You may use self.inferior.as_mut().unwrap() to get a mutable reference
-                        // to the Inferior object
-                        match self.inferior.as_mut().unwrap().continue_run(&self.break_list).ok().unwrap() {
-                            Status::Exited(exit_code) => println!("Child exit (status {})", exit_code),
-                            Status::Signaled(signal)  => println!("Child exit due to {}", signal),
-                            Status::Stopped(signal, rip) => {
-                                match self.debug_data.get_line_from_addr(rip) {
-                                    Some(val) => println!("Child stopped (signal {}) at {}", signal, val),                   
-                                    None      => println!("Child stopped (signal {}) at {:#?}", signal, rip),
-                                } 
-                            }
-                        } 
-                    } else {
-                        println!("Error starting subprocess");
+                    match Inferior::new(&self.target, &args, &mut self.break_list) {
+                        Ok(inferior) => {
+                            // Create the inferior
+                            self.inferior = Some(inferior);
+                            self.reported_thread = 1;
+
+                            self.arm_watchpoints();
+                            self.resume(|inferior, _, break_list, stop_at| {
+                                inferior.continue_run(break_list, stop_at)
+                            });
+                        }
+                        Err(err) => println!("Error starting subprocess: {}", err),
                     }
                 }
                 DebuggerCommand::Quit => {
-                    if self.inferior.is_some() {
-                        let child = self.inferior.as_mut().unwrap();
+                    if self.inferior.as_ref().is_some_and(|inferior| inferior.is_attached()) {
+                        // Processes deet attached to were running before; leave them running
+                        self.detach();
+                    } else if let Some(child) = self.inferior.as_mut() {
                         println!("Killing running inferior (pid {})", child.pid());
-                        child.kill();
+                        if let Err(err) = child.kill() {
+                            println!("Error killing inferior: {}", err);
+                        }
                         self.inferior = None;
                     }
-                    return;
+                    for (_, mut inferior) in self.other_inferiors.drain(..) {
+                        // These are exiting along with deet; there is nothing to do about errors
+                        let _ = inferior.kill();
+                    }
+                    return self.crash.map_or(0, |signal| 128 + signal as i32);
                 }
+                DebuggerCommand::Source(path) => match read_script(&path) {
+                    // The script's commands run before whatever comes after `source`
+                    Ok(lines) => {
+                        for line in lines.into_iter().rev() {
+                            self.script_lines.push_front(line);
+                        }
+                    }
+                    Err(err) => println!("{}: {}", path, err),
+                },
+                DebuggerCommand::Attach(pid) => match pid.parse::<i32>() {
+                    Ok(pid) => self.attach(Pid::from_raw(pid)),
+                    Err(_) => println!("Invalid process id '{}'", pid),
+                },
+                DebuggerCommand::Detach => self.detach(),
                 DebuggerCommand::Cont => {
+                    self.resume(|inferior, _, break_list, stop_at| {
+                        inferior.continue_run(break_list, stop_at)
+                    });
+                }
+                DebuggerCommand::Back => {
                     if self.inferior.is_none() {
                         println!("No child is processing!");
                         continue;
                     }
-                    match self.inferior.as_mut().unwrap().continue_run(&self.break_list).ok().unwrap() {
-                            Status::Exited(exit_code) => {
-                                println!("Child exit (status {})", exit_code);
-                                self.inferior = None;
-                            }
-                            Status::Signaled(signal)  => {
-                                println!("Child exit due to {}", signal);
-                                self.inferior = None;
+                    self.print_backtrace();
+                }
+                DebuggerCommand::Up(ref count) | DebuggerCommand::Down(ref count) => {
+                    let count = match count.as_deref().map(str::parse::<usize>) {
+                        None => 1,
+                        Some(Ok(count)) => count,
+                        Some(Err(_)) => {
+                            println!("Invalid frame count");
+                            continue;
+                        }
+                    };
+                    let up = matches!(cmd, DebuggerCommand::Up(_));
+                    self.move_frame(up, count);
+                }
+                DebuggerCommand::Frame(level) => match level.as_deref().map(str::parse::<usize>) {
+                    None => self.select_frame(self.selected_frame),
+                    Some(Ok(level)) => self.select_frame(level),
+                    Some(Err(_)) => println!("Invalid frame number"),
+                },
+                DebuggerCommand::Break(args) => {
+                    let (locations, condition) = match args.iter().position(|arg| arg == "if") {
+                        Some(index) => match Condition::parse(&args[index + 1..].join(" "), &self.debug_data) {
+                            Ok(condition) => (&args[..index], Some(condition)),
+                            Err(err) => {
+                                println!("{}", err);
+                                continue;
                             }
-                            Status::Stopped(signal, rip) => {
-                                match self.debug_data.get_line_from_addr(rip) {
-                                    Some(val) => println!("Child stopped (signal {}) at {}", signal, val),
-                                    None      => println!("Child stopped (signal {}) at {:#?}", signal, rip),
-                                } 
+                        },
+                        None => (&args[..], None),
+                    };
+                    for string in locations {
+                        match self.parse_address(string) {
+                            Ok(addr) => self.add_breakpoint(addr, condition.clone()),
+                            Err(err) => println!("{}", err),
+                        }
+                    }
+                }
+                DebuggerCommand::Ignore(args) => {
+                    let parsed = match args.as_slice() {
+                        [id, count] => id.parse::<usize>().ok().zip(count.parse::<usize>().ok()),
+                        _ => None,
+                    };
+                    let (id, count) = match parsed {
+                        Some(parsed) => parsed,
+                        None => {
+                            println!("Usage: ignore <breakpoint number> <count>");
+                            continue;
+                        }
+                    };
+                    match self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+                        Some(bp) => {
+                            bp.ignore_count = count;
+                            println!("Will ignore next {} crossings of breakpoint {}.", count, id);
+                        }
+                        None => println!("No breakpoint number {}.", id),
+                    }
+                }
+                DebuggerCommand::InfoBreakpoints => self.print_breakpoints(),
+                DebuggerCommand::Commands(id) => self.read_breakpoint_commands(id.as_deref()),
+                DebuggerCommand::Inferior(id) => match id.as_deref().map(str::parse::<usize>) {
+                    None => self.print_current_inferior(),
+                    Some(Ok(id)) => self.switch_inferior(id),
+                    Some(Err(_)) => println!("Invalid inferior number"),
+                },
+                DebuggerCommand::InfoInferiors => self.print_inferiors(),
+                DebuggerCommand::Thread(id) => match id.as_deref().map(str::parse::<usize>) {
+                    None => self.print_current_thread(),
+                    Some(Ok(id)) => self.switch_thread(id),
+                    Some(Err(_)) => println!("Invalid thread ID"),
+                },
+                DebuggerCommand::InfoThreads => self.print_threads(),
+                DebuggerCommand::Handle(args) => self.handle_signals(&args),
+                DebuggerCommand::InfoSignals(name) => match name.as_deref().map(parse_signal) {
+                    None => self.print_signals(&[]),
+                    Some(Ok(signal)) => self.print_signals(&[signal]),
+                    Some(Err(err)) => println!("{}", err),
+                },
+                DebuggerCommand::Signal(name) => {
+                    // `signal 0` continues without a signal, even if the program was stopped by one
+                    let signal = match (name.as_str(), parse_signal(&name)) {
+                        ("0", _) => None,
+                        (_, Ok(signal)) => Some(signal),
+                        (_, Err(err)) => {
+                            println!("{}", err);
+                            continue;
+                        }
+                    };
+                    match self.inferior.as_mut() {
+                        Some(inferior) => inferior.set_pending_signal(signal),
+                        None => {
+                            println!("The program is not being run.");
+                            continue;
+                        }
+                    }
+                    match signal {
+                        Some(signal) => println!("Continuing with signal {}.", signal),
+                        None => println!("Continuing with no signal."),
+                    }
+                    self.resume(|inferior, _, break_list, stop_at| {
+                        inferior.continue_run(break_list, stop_at)
+                    });
+                }
+                DebuggerCommand::Examine(spec, addr) => self.examine_memory(&spec, &addr),
+                DebuggerCommand::Disassemble(function) => self.disassemble_function(function.as_deref()),
+                DebuggerCommand::InfoRegisters(names) => self.print_registers(&names),
+                DebuggerCommand::Set(assignment) => {
+                    if self.set_fork_option(&assignment) {
+                        continue;
+                    }
+                    let (lhs, rhs) = match assignment.split_once('=') {
+                        Some((lhs, rhs)) => (lhs.trim(), rhs.trim()),
+                        None => {
+                            println!("Usage: set $register = value, set var name = value or set *ADDR = bytes");
+                            continue;
+                        }
+                    };
+                    if let Some(name) = lhs.strip_prefix('$') {
+                        self.set_register(name, rhs);
+                    } else if let Some(addr) = lhs.strip_prefix('*') {
+                        self.set_memory(addr, rhs);
+                    } else {
+                        // gdb lets `var` be left out when the name can't be mistaken for a setting
+                        let name = lhs.strip_prefix("var ").unwrap_or(lhs).trim();
+                        self.set_variable(name, rhs);
+                    }
+                }
+                DebuggerCommand::Delete(args) => {
+                    for id in self.parse_breakpoint_ids(&args) {
+                        self.delete_breakpoint(id);
+                    }
+                }
+                DebuggerCommand::Disable(args) => {
+                    for id in self.parse_breakpoint_ids(&args) {
+                        self.set_breakpoint_enabled(id, false);
+                    }
+                }
+                DebuggerCommand::Enable(args) => {
+                    for id in self.parse_breakpoint_ids(&args) {
+                        self.set_breakpoint_enabled(id, true);
+                    }
+                }
+                DebuggerCommand::Watch(expression) => self.add_watchpoint(&expression),
+                DebuggerCommand::List(arg) => match self.resolve_list_location(arg.as_deref()) {
+                    Ok((path, line, centered)) => self.list_source(path, line, centered),
+                    Err(err) => println!("{}", err),
+                },
+                DebuggerCommand::Print(expression) => {
+                    if expression.is_empty() {
+                        if self.inferior.is_none() {
+                            println!("No child is processing!");
+                            continue;
+                        }
+                        self.print_locals();
+                    } else {
+                        self.print_expression(&expression);
+                    }
+                }
+                DebuggerCommand::Display(expression) => {
+                    if expression.is_empty() {
+                        self.show_displays();
+                    } else {
+                        self.add_display(&expression);
+                    }
+                }
+                DebuggerCommand::Undisplay(args) => {
+                    if args.is_empty() {
+                        self.displays.clear();
+                    }
+                    for arg in args {
+                        match arg.parse::<usize>() {
+                            Ok(id) if self.displays.iter().any(|display| display.id == id) => {
+                                self.displays.retain(|display| display.id != id);
                             }
-                    } 
+                            _ => println!("No display number {}.", arg),
+                        }
+                    }
                 }
-                DebuggerCommand::Back => {
+                DebuggerCommand::InfoDisplay => self.info_display(),
+                DebuggerCommand::InfoLocals => self.print_locals(),
+                DebuggerCommand::Step | DebuggerCommand::Next => {
+                    let step_into = matches!(cmd, DebuggerCommand::Step);
+                    self.resume(|inferior, debug_data, break_list, stop_at| {
+                        inferior.step_line(debug_data, break_list, step_into, stop_at)
+                    });
+                }
+                DebuggerCommand::StepInstruction(ref count) | DebuggerCommand::NextInstruction(ref count) => {
+                    let count = match count.as_deref().map(str::parse::<usize>) {
+                        None => 1,
+                        Some(Ok(count)) => count,
+                        Some(Err(_)) => {
+                            println!("Invalid instruction count");
+                            continue;
+                        }
+                    };
+                    let step_over_calls = matches!(cmd, DebuggerCommand::NextInstruction(_));
+                    self.resume(|inferior, _, break_list, stop_at| {
+                        inferior.step_instructions(count, step_over_calls, break_list, stop_at)
+                    });
+                    // Show where we are at the instruction level as well
+                    if let Some(rip) = self.inferior.as_ref().and_then(|inferior| inferior.get_rip().ok()) {
+                        self.examine_instructions(rip, 1);
+                    }
+                }
+                DebuggerCommand::Finish => {
                     if self.inferior.is_none() {
                         println!("No child is processing!");
                         continue;
                     }
-                    self.inferior.as_ref().unwrap().print_backtrace(&self.debug_data).expect("Back trace fail!");
+                    // Finish the selected frame, which returns to the one above it
+                    let frame = match self.selected_frame() {
+                        Ok(frame) => frame,
+                        Err(err) => {
+                            println!("{}", err);
+                            continue;
+                        }
+                    };
+                    match self.frame_function(&frame).as_deref() {
+                        Some("main") | None => {
+                            println!("\"finish\" not meaningful in the outermost frame.");
+                            continue;
+                        }
+                        // An inlined call has no return address to stop at
+                        Some(name) if self.is_inlined(&frame) => {
+                            println!("\"finish\" out of the inlined function {} is not supported.", name);
+                            continue;
+                        }
+                        Some(name) => println!("Run till exit from {}", name),
+                    }
+                    let level = frame.level;
+                    self.resume(|inferior, _, break_list, stop_at| {
+                        inferior.finish(level, break_list, stop_at)
+                    });
                 }
-                DebuggerCommand::Break(args) => {
-                    for string in args {
-                        let addr = self.parse_address(&string).unwrap();
-                        if self.inferior.is_some() {
-                            if let Ok(inst) = self.inferior.as_mut().unwrap().write_byte(addr, 0xcc) {
-                                println!("Set break point {} at {:#x}", self.break_list.len(), addr);
-                                self.break_list.insert(addr, inst);
-                            } else {
-                                println!("Invalid breakpoint at {:#x}", addr);
-                            }
+            }
+        }
+    }
+
+    /// Attaches to the running process `pid`, which is left stopped where it was.
+    pub fn attach(&mut self, pid: Pid) {
+        if self.inferior.is_some() {
+            println!("A program is being debugged already. Detach or kill it first.");
+            return;
+        }
+        // The debugging symbols come from the target, so they are only useful for the same binary
+        let exe = fs::read_link(format!("/proc/{}/exe", pid));
+        if let (Ok(exe), Ok(target)) = (exe, fs::canonicalize(&self.target)) {
+            if exe != target {
+                println!("warning: process {} is running {}, not {}", pid, exe.display(), self.target);
+            }
+        }
+        match Inferior::attach(pid, &mut self.break_list) {
+            Ok(inferior) => {
+                println!("Attaching to process {}", pid);
+                let rip = inferior.get_rip().unwrap_or_default();
+                self.inferior = Some(inferior);
+                self.arm_watchpoints();
+                self.report_status(Ok(Status::Stopped(Signal::SIGSTOP, rip)), &[], &[]);
+            }
+            Err(err) => println!("Could not attach to process {}: {}", pid, err),
+        }
+    }
+
+    /// Removes all breakpoints from the inferior and lets it run on without the debugger.
+    fn detach(&mut self) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        let pid = inferior.pid();
+        let no_breakpoints = HashMap::new();
+        let break_list =
+            if inferior.runs_other_program() { &no_breakpoints } else { &self.break_list };
+        match inferior.detach(break_list) {
+            Ok(()) => println!("Detached from process {}", pid),
+            Err(err) => println!("Error detaching from process {}: {}", pid, err),
+        }
+        self.inferior = None;
+    }
+
+    /// Creates a new enabled breakpoint at `addr`.
+    fn add_breakpoint(&mut self, addr: usize, condition: Option<Condition>) {
+        if !self.insert_breakpoint_byte(addr) {
+            println!("Invalid breakpoint at {:#x}", addr);
+            return;
+        }
+        let bp = Breakpoint::new(
+            self.next_breakpoint_id,
+            addr,
+            self.debug_data.get_function_from_addr(addr),
+            self.debug_data.get_line_from_addr(addr),
+            condition,
+        );
+        self.next_breakpoint_id += 1;
+        println!("Set break point {} at {:#x}: {}", bp.id, addr, bp);
+        self.breakpoints.push(bp);
+    }
+
+    /// Deletes the breakpoint or watchpoint numbered `id`.
+    fn delete_breakpoint(&mut self, id: usize) {
+        if let Some(index) = self.breakpoints.iter().position(|bp| bp.id == id) {
+            let bp = self.breakpoints.remove(index);
+            self.remove_breakpoint_byte(bp.addr);
+        } else if let Some(index) = self.watchpoints.iter().position(|wp| wp.id == id) {
+            self.watchpoints.remove(index);
+            self.arm_watchpoints();
+        } else {
+            println!("No breakpoint number {}.", id);
+        }
+    }
+
+    /// Enables or disables the breakpoint or watchpoint numbered `id`.
+    fn set_breakpoint_enabled(&mut self, id: usize, enabled: bool) {
+        if let Some(index) = self.breakpoints.iter().position(|bp| bp.id == id) {
+            let addr = self.breakpoints[index].addr;
+            if !enabled {
+                self.breakpoints[index].enabled = false;
+                self.remove_breakpoint_byte(addr);
+            } else if self.insert_breakpoint_byte(addr) {
+                self.breakpoints[index].enabled = true;
+            } else {
+                println!("Invalid breakpoint at {:#x}", addr);
+            }
+        } else if let Some(index) = self.watchpoints.iter().position(|wp| wp.id == id) {
+            if enabled && !self.watchpoints[index].enabled && !self.has_free_watchpoint_slot() {
+                println!("Hardware watchpoint limit ({}) reached.", NUM_WATCHPOINT_SLOTS);
+                return;
+            }
+            self.watchpoints[index].enabled = enabled;
+            self.arm_watchpoints();
+        } else {
+            println!("No breakpoint number {}.", id);
+        }
+    }
+
+    /// Creates a hardware watchpoint on a variable, or on the word at `*ADDR`.
+    fn add_watchpoint(&mut self, expression: &str) {
+        let (addr, entity_type) = match self.resolve_watch_target(expression) {
+            Ok(target) => target,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let len = entity_type.size;
+        if ![1, 2, 4, 8].contains(&len) || addr % len != 0 {
+            println!(
+                "Cannot watch {}: hardware watchpoints need an aligned value of 1, 2, 4 or 8 bytes",
+                expression
+            );
+            return;
+        }
+        if !self.has_free_watchpoint_slot() {
+            println!("Hardware watchpoint limit ({}) reached.", NUM_WATCHPOINT_SLOTS);
+            return;
+        }
+        let wp = Watchpoint::new(self.next_breakpoint_id, expression.to_string(), addr, entity_type);
+        self.next_breakpoint_id += 1;
+        println!("Hardware watchpoint {}: {}", wp.id, wp.expression);
+        self.watchpoints.push(wp);
+        self.arm_watchpoints();
+    }
+
+    /// Finds the address and type of what `watch` was asked to watch: `*ADDR` or an lvalue
+    /// expression like `global` or `p->next->value`.
+    fn resolve_watch_target(&self, expression: &str) -> Result<(usize, Type), String> {
+        if let Some(addr) = expression.strip_prefix('*').and_then(|addr| parse_integer(addr.trim())) {
+            return Ok((addr as usize, Type::new("long int".to_string(), size_of::<usize>())));
+        }
+        let expression = Expression::parse(expression, &self.debug_data)?;
+        self.with_context(|ctx| expression.evaluate_lvalue(ctx))
+    }
+
+    fn has_free_watchpoint_slot(&self) -> bool {
+        self.watchpoints.iter().filter(|wp| wp.enabled).count() < NUM_WATCHPOINT_SLOTS
+    }
+
+    /// Programs the enabled watchpoints into the inferior's debug registers and records the
+    /// current values of the watched memory.
+    fn arm_watchpoints(&mut self) {
+        // The watched variables belong to the program being debugged
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) if !inferior.runs_other_program() => inferior,
+            _ => return,
+        };
+        let mut free_slots = 0..NUM_WATCHPOINT_SLOTS;
+        for wp in self.watchpoints.iter_mut() {
+            wp.slot = if wp.enabled { free_slots.next() } else { None };
+            if wp.slot.is_some() {
+                wp.update_value(inferior);
+            }
+        }
+        for slot in 0..NUM_WATCHPOINT_SLOTS {
+            let watch = self
+                .watchpoints
+                .iter()
+                .find(|wp| wp.slot == Some(slot))
+                .map(|wp| (wp.addr, wp.entity_type.size));
+            if let Err(err) = inferior.set_hw_watchpoint(slot, watch) {
+                println!("Could not program debug register {}: {}", slot, err);
+            }
+        }
+    }
+
+    /// Makes sure there is a 0xcc at `addr`, recording the original byte in break_list. Returns
+    /// false if the inferior's memory could not be written.
+    fn insert_breakpoint_byte(&mut self, addr: usize) -> bool {
+        if self.break_list.contains_key(&addr) {
+            return true;
+        }
+        // Every inferior runs the same program, so the original byte is the same in all of them
+        let mut orig_byte = None;
+        for inferior in self.program_inferiors() {
+            match inferior.write_byte(addr, 0xcc) {
+                Ok(byte) => orig_byte = Some(byte),
+                Err(_) => return false,
+            }
+        }
+        // Without an inferior, Inferior::new fills in the original byte once the program is
+        // started
+        self.break_list.insert(addr, orig_byte.unwrap_or(0));
+        true
+    }
+
+    /// Restores the original byte at `addr`, unless another enabled breakpoint still needs the
+    /// 0xcc there.
+    fn remove_breakpoint_byte(&mut self, addr: usize) {
+        if self.breakpoints.iter().any(|bp| bp.enabled && bp.addr == addr) {
+            return;
+        }
+        if let Some(orig_byte) = self.break_list.remove(&addr) {
+            for inferior in self.program_inferiors() {
+                if let Err(err) = inferior.write_byte(addr, orig_byte) {
+                    println!("Could not remove breakpoint at {:#x}: {}", addr, err);
+                }
+            }
+        }
+    }
+
+    /// Returns the inferiors running the program being debugged, which the breakpoints are
+    /// written into.
+    fn program_inferiors(&mut self) -> impl Iterator<Item = &mut Inferior> {
+        self.inferior
+            .iter_mut()
+            .chain(self.other_inferiors.iter_mut().map(|(_, inferior)| inferior))
+            .filter(|inferior| !inferior.runs_other_program())
+    }
+
+    /// Handles `handle SIGNAL... ACTION...`, which changes whether the signals stop the inferior
+    /// and whether they are passed to it, and shows the new policies.
+    fn handle_signals(&mut self, args: &[String]) {
+        let mut signals = Vec::new();
+        let mut actions = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "stop" | "nostop" | "pass" | "nopass" | "ignore" | "noignore" => {
+                    actions.push(arg.as_str())
+                }
+                "all" => signals.extend(
+                    self.signals
+                        .iter()
+                        .map(|(signal, _)| signal)
+                        // Like gdb, leave out the debugger's own signals, and those that can't be
+                        // caught or passed at all
+                        .filter(|signal| {
+                            ![Signal::SIGINT, Signal::SIGTRAP, Signal::SIGSTOP, Signal::SIGKILL]
+                                .contains(signal)
+                        }),
+                ),
+                _ => match parse_signal(arg) {
+                    Ok(signal) => signals.push(signal),
+                    Err(_) => {
+                        println!("Unrecognized or ambiguous flag word: \"{}\".", arg);
+                        return;
+                    }
+                },
+            }
+        }
+        if signals.is_empty() {
+            println!("Usage: handle SIGNAL... stop|nostop|pass|nopass");
+            return;
+        }
+        // A SIGSTOP passed on would stop the process again as soon as it resumes
+        let passes = actions.iter().any(|action| *action == "pass" || *action == "noignore");
+        if passes && signals.contains(&Signal::SIGSTOP) {
+            println!("SIGSTOP is used by the debugger.");
+            return;
+        }
+        for signal in &signals {
+            let policy = self.signals.get_mut(*signal);
+            for action in &actions {
+                match *action {
+                    "stop" => policy.stop = true,
+                    "nostop" => policy.stop = false,
+                    "pass" | "noignore" => policy.pass = true,
+                    _ => policy.pass = false,
+                }
+            }
+        }
+        self.print_signals(&signals);
+    }
+
+    /// Lists how `signals` are handled, or every signal if `signals` is empty.
+    fn print_signals(&self, signals: &[Signal]) {
+        println!("{:<14}{:<6}{:<17}Description", "Signal", "Stop", "Pass to program");
+        for (signal, policy) in self.signals.iter() {
+            if !signals.is_empty() && !signals.contains(&signal) {
+                continue;
+            }
+            let yes_no = |value| if value { "Yes" } else { "No" };
+            println!(
+                "{:<14}{:<6}{:<17}{}",
+                signal.as_str(),
+                yes_no(policy.stop),
+                yes_no(policy.pass),
+                describe_signal(signal)
+            );
+        }
+    }
+
+    /// Handles `set follow-fork-mode parent|child` and `set detach-on-fork on|off`. Returns false
+    /// if `setting` is about something else.
+    fn set_fork_option(&mut self, setting: &str) -> bool {
+        let mut words = setting.split_whitespace();
+        let (name, value) = match (words.next(), words.next(), words.next()) {
+            (Some(name), value, None) if name == "follow-fork-mode" || name == "detach-on-fork" => {
+                (name, value.unwrap_or(""))
+            }
+            _ => return false,
+        };
+        match (name, value) {
+            ("follow-fork-mode", "parent") => self.fork_policy.follow_child = false,
+            ("follow-fork-mode", "child") => self.fork_policy.follow_child = true,
+            ("follow-fork-mode", _) => println!("Usage: set follow-fork-mode parent|child"),
+            (_, "on") | (_, "") => self.fork_policy.detach = true,
+            (_, "off") => self.fork_policy.detach = false,
+            _ => println!("Usage: set detach-on-fork on|off"),
+        }
+        true
+    }
+
+    /// Shows which inferior commands act on.
+    fn print_current_inferior(&self) {
+        match self.inferior.as_ref() {
+            Some(inferior) => println!(
+                "[Current inferior is {} [process {}] ({})]",
+                self.inferior_id,
+                inferior.pid(),
+                inferior.exe_path().display()
+            ),
+            None => {
+                println!("[Current inferior is {} [<null>] ({})]", self.inferior_id, self.target)
+            }
+        }
+    }
+
+    /// Makes inferior `id` the current one and shows where it is stopped.
+    fn switch_inferior(&mut self, id: usize) {
+        if id == self.inferior_id {
+            self.print_current_inferior();
+            return;
+        }
+        let index = match self.other_inferiors.iter().position(|(other_id, _)| *other_id == id) {
+            Some(index) => index,
+            None => {
+                println!("Inferior ID {} not known.", id);
+                return;
+            }
+        };
+        let (_, inferior) = self.other_inferiors.remove(index);
+        println!("[Switching to inferior {} [process {}]]", id, inferior.pid());
+        // An inferior whose process is gone is forgotten, as after it exits
+        if let Some(previous) = self.inferior.replace(inferior) {
+            self.other_inferiors.push((self.inferior_id, previous));
+            self.other_inferiors.sort_by_key(|(id, _)| *id);
+        }
+        self.inferior_id = id;
+        self.reported_thread = self.inferior.as_ref().unwrap().current_thread().id;
+        self.arm_watchpoints();
+        self.select_frame(0);
+    }
+
+    fn print_current_thread(&self) {
+        match self.inferior.as_ref() {
+            Some(inferior) => {
+                let thread = inferior.current_thread();
+                println!("[Current thread is {} (LWP {})]", thread.id, thread.tid);
+            }
+            None => println!("No thread selected."),
+        }
+    }
+
+    /// Makes thread `id` of the current inferior the current thread and shows where it is
+    /// stopped.
+    fn switch_thread(&mut self, id: usize) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("No child is processing!");
+                return;
+            }
+        };
+        if !inferior.select_thread(id) {
+            println!("Invalid thread ID: {}", id);
+            return;
+        }
+        println!("[Switching to thread {} (LWP {})]", id, inferior.current_thread().tid);
+        self.reported_thread = id;
+        self.select_frame(0);
+    }
+
+    /// Lists the threads of the current inferior with the innermost frame of each.
+    fn print_threads(&mut self) {
+        let (curr_id, threads) = match self.inferior.as_ref() {
+            Some(inferior) => (
+                inferior.current_thread().id,
+                inferior.threads().iter().map(|thread| (thread.id, thread.tid)).collect::<Vec<_>>(),
+            ),
+            None => {
+                println!("No threads.");
+                return;
+            }
+        };
+        println!("  Id   Target Id                 Frame");
+        for (id, tid) in threads {
+            self.inferior.as_mut().unwrap().select_thread(id);
+            let frame = match self.stack_frames() {
+                Ok(frames) if !frames.is_empty() => self.describe_frame(&frames[0]),
+                Ok(_) => "??".to_string(),
+                Err(err) => err,
+            };
+            let pid = self.inferior.as_ref().unwrap().pid();
+            let name = fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, tid)).unwrap_or_default();
+            let target_id = format!("LWP {} \"{}\"", tid, name.trim_end());
+            let marker = if id == curr_id { '*' } else { ' ' };
+            println!("{} {:<4} {:<25} {}", marker, id, target_id, frame);
+        }
+        self.inferior.as_mut().unwrap().select_thread(curr_id);
+    }
+
+    fn print_inferiors(&self) {
+        let mut inferiors: Vec<(usize, Option<&Inferior>)> = self
+            .other_inferiors
+            .iter()
+            .map(|(id, inferior)| (*id, Some(inferior)))
+            .collect();
+        inferiors.push((self.inferior_id, self.inferior.as_ref()));
+        inferiors.sort_by_key(|(id, _)| *id);
+        println!("  Num  Description       Executable");
+        for (id, inferior) in inferiors {
+            let (description, executable) = match inferior {
+                Some(inferior) => (
+                    format!("process {}", inferior.pid()),
+                    inferior.exe_path().display().to_string(),
+                ),
+                None => ("<null>".to_string(), self.target.clone()),
+            };
+            let marker = if id == self.inferior_id { '*' } else { ' ' };
+            println!("{} {:<4} {:<17} {}", marker, id, description, executable);
+        }
+    }
+
+    /// Parses the breakpoint numbers given to delete/disable/enable. No arguments means every
+    /// breakpoint.
+    fn parse_breakpoint_ids(&self, args: &[String]) -> Vec<usize> {
+        if args.is_empty() {
+            let bp_ids = self.breakpoints.iter().map(|bp| bp.id);
+            return bp_ids.chain(self.watchpoints.iter().map(|wp| wp.id)).collect();
+        }
+        args.iter()
+            .filter_map(|arg| {
+                let id = arg.parse().ok();
+                if id.is_none() {
+                    println!("Bad breakpoint number '{}'", arg);
+                }
+                id
+            })
+            .collect()
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            println!("No breakpoints.");
+            return;
+        }
+        println!("{:<8}{:<5}{:<20}{:<6}What", "Num", "Enb", "Address", "Hits");
+        // Breakpoints and watchpoints share their numbering, so list them together in order
+        let mut ids: Vec<usize> = self
+            .breakpoints
+            .iter()
+            .map(|bp| bp.id)
+            .chain(self.watchpoints.iter().map(|wp| wp.id))
+            .collect();
+        ids.sort_unstable();
+        for id in ids {
+            if let Some(bp) = self.breakpoints.iter().find(|bp| bp.id == id) {
+                println!(
+                    "{:<8}{:<5}{:<20}{:<6}{}",
+                    bp.id,
+                    if bp.enabled { "y" } else { "n" },
+                    format!("{:#018x}", bp.addr),
+                    bp.hit_count,
+                    bp
+                );
+                if let Some(condition) = &bp.condition {
+                    println!("        stop only if {}", condition);
+                }
+                if bp.ignore_count > 0 {
+                    println!("        Will ignore next {} crossings of breakpoint.", bp.ignore_count);
+                }
+                for command in &bp.commands {
+                    println!("        {}", command);
+                }
+            } else if let Some(wp) = self.watchpoints.iter().find(|wp| wp.id == id) {
+                println!(
+                    "{:<8}{:<5}{:<20}{:<6}hw watchpoint {}",
+                    wp.id,
+                    if wp.enabled { "y" } else { "n" },
+                    format!("{:#018x}", wp.addr),
+                    wp.hit_count,
+                    wp.expression
+                );
+                for command in &wp.commands {
+                    println!("        {}", command);
+                }
+            }
+        }
+    }
+
+    /// Reads the commands to run when breakpoint `id` stops the inferior, one per line up to
+    /// `end`, replacing the ones it had. Without `id`, the last breakpoint set is used.
+    fn read_breakpoint_commands(&mut self, id: Option<&str>) {
+        let id = match id.map(str::parse::<usize>) {
+            None if self.next_breakpoint_id == 1 => {
+                println!("No breakpoints specified.");
+                return;
+            }
+            None => self.next_breakpoint_id - 1,
+            Some(Ok(id)) => id,
+            Some(Err(_)) => {
+                println!("Invalid breakpoint number");
+                return;
+            }
+        };
+        if !self.breakpoints.iter().any(|bp| bp.id == id)
+            && !self.watchpoints.iter().any(|wp| wp.id == id)
+        {
+            println!("No breakpoint number {}.", id);
+            return;
+        }
+        if self.script_lines.is_empty() && self.breakpoint_commands.is_empty() {
+            println!("Type commands for breakpoint {}, one per line.", id);
+            println!("End with a line saying just \"end\".");
+        }
+        let mut commands = Vec::new();
+        // Running out of input ends the list too
+        while let Some(line) = self.next_line(">") {
+            match line.trim() {
+                "end" => break,
+                "" => {}
+                command => commands.push(command.to_string()),
+            }
+        }
+        if let Some(bp) = self.breakpoints.iter_mut().find(|bp| bp.id == id) {
+            bp.commands = commands;
+        } else if let Some(wp) = self.watchpoints.iter_mut().find(|wp| wp.id == id) {
+            wp.commands = commands;
+        }
+    }
+
+    /// Resumes the inferior using `resume` and reports where it stopped. Breakpoints whose
+    /// condition is false or whose ignore count has not run out are passed over transparently.
+    /// When the inferior forks or execs, the process deet follows is continued.
+    fn resume<F>(&mut self, resume: F)
+    where
+        F: FnOnce(
+            &mut Inferior,
+            &DwarfData,
+            &HashMap<usize, u8>,
+            &mut dyn FnMut(&Inferior, Trap) -> bool,
+        ) -> Result<Status, DebuggerError>,
+    {
+        if self.inferior.is_none() {
+            println!("No child is processing!");
+            return;
+        }
+        self.breakpoint_commands.clear();
+        let (mut status, mut hits, mut watch_hits) = self.resume_once(resume);
+        while self.handle_process_events() {
+            (status, hits, watch_hits) = self.resume_once(|inferior, _, break_list, stop_at| {
+                inferior.continue_run(break_list, stop_at)
+            });
+        }
+        self.report_status(status, &hits, &watch_hits);
+    }
+
+    /// Resumes the current inferior using `resume`. Returns how it stopped, along with the
+    /// breakpoints and watchpoints (with their previous values) that stopped it.
+    fn resume_once<F>(
+        &mut self,
+        resume: F,
+    ) -> (Result<Status, DebuggerError>, Vec<usize>, WatchHits)
+    where
+        F: FnOnce(
+            &mut Inferior,
+            &DwarfData,
+            &HashMap<usize, u8>,
+            &mut dyn FnMut(&Inferior, Trap) -> bool,
+        ) -> Result<Status, DebuggerError>,
+    {
+        let inferior = self.inferior.as_mut().unwrap();
+        inferior.set_fork_policy(self.fork_policy);
+        inferior.set_signal_table(&self.signals);
+        let no_breakpoints = HashMap::new();
+        let break_list =
+            if inferior.runs_other_program() { &no_breakpoints } else { &self.break_list };
+        let debug_data = &self.debug_data;
+        let breakpoints = &mut self.breakpoints;
+        let watchpoints = &mut self.watchpoints;
+        let mut hits = Vec::new();
+        let mut watch_hits = Vec::new();
+        let status = resume(inferior, debug_data, break_list, &mut |inferior, trap| {
+            hits.clear();
+            watch_hits.clear();
+            match trap {
+                Trap::Breakpoint(addr) => {
+                    if !breakpoints.iter().any(|bp| bp.enabled && bp.addr == addr) {
+                        return true;
+                    }
+                    hits = breakpoint::check_hits(breakpoints, addr, inferior, debug_data);
+                    !hits.is_empty()
+                }
+                Trap::Watchpoint(slots) => {
+                    watch_hits = breakpoint::check_watch_hits(watchpoints, &slots, inferior);
+                    !watch_hits.is_empty()
+                }
+            }
+        });
+        (status, hits, watch_hits)
+    }
+
+    /// Reports the forks and execs of the current inferior during the last resume, and keeps
+    /// track of the processes they created. Returns true if the inferior stopped to hand over to a
+    /// forked child or a new program, which should then be continued.
+    fn handle_process_events(&mut self) -> bool {
+        let (pid, events) = match self.inferior.as_mut() {
+            Some(inferior) => (inferior.pid(), inferior.take_events()),
+            None => return false,
+        };
+        let mut handed_over = false;
+        for event in events {
+            match event {
+                ProcessEvent::Forked(child) => {
+                    println!("[New inferior {} (process {})]", self.next_inferior_id, child.pid());
+                    self.other_inferiors.push((self.next_inferior_id, child));
+                    self.next_inferior_id += 1;
+                }
+                ProcessEvent::DetachedFork(child_pid) => {
+                    println!("[Detaching after fork from child process {}]", child_pid)
+                }
+                ProcessEvent::FollowFork(child) => {
+                    let child_pid = child.pid();
+                    println!(
+                        "[Attaching after process {} fork to child process {}]",
+                        pid, child_pid
+                    );
+                    let mut parent = self.inferior.replace(child).unwrap();
+                    if self.fork_policy.detach {
+                        // After a vfork, this takes the breakpoints out of the child too, as it
+                        // shares the parent's memory until it execs
+                        let no_breakpoints = HashMap::new();
+                        let break_list = if parent.runs_other_program() {
+                            &no_breakpoints
                         } else {
-                            println!("Set break point {} at {:#x}", self.break_list.len(), addr);
-                            self.break_list.insert(addr, 0);
+                            &self.break_list
+                        };
+                        if let Err(err) = parent.detach(break_list) {
+                            println!("Error detaching from process {}: {}", pid, err);
                         }
+                        println!("[Detaching after fork from parent process {}]", pid);
+                    } else {
+                        // The parent keeps its number, and the child becomes the new inferior
+                        println!(
+                            "[New inferior {} (process {})]",
+                            self.next_inferior_id, child_pid
+                        );
+                        self.other_inferiors.push((self.inferior_id, parent));
+                        self.inferior_id = self.next_inferior_id;
+                        self.next_inferior_id += 1;
                     }
+                    self.reported_thread = 1;
+                    self.arm_watchpoints();
+                    handed_over = true;
+                }
+                ProcessEvent::NewThread(id, tid) => println!("[New thread {} (LWP {})]", id, tid),
+                ProcessEvent::ThreadExited(id, tid) => {
+                    println!("[Thread {} (LWP {}) exited]", id, tid)
+                }
+                ProcessEvent::Exec(path) => {
+                    println!("process {} is executing new program: {}", pid, path);
+                    self.arm_watchpoints();
+                    handed_over = true;
                 }
             }
         }
+        handed_over
     }
 
-    fn parse_address(&self, addr: &str) -> Option<usize> {
-        if addr.starts_with('*') {
-            let addr_without_0x = if addr[1..].to_lowercase().starts_with("0x") {
-                &addr[3..]
+    /// Reports why the inferior stopped after resuming it, and forgets about it if it is no longer
+    /// alive. `hits` are the breakpoints that caused the stop, and `watch_hits` the watchpoints
+    /// along with their previous values.
+    fn report_status(
+        &mut self,
+        status: Result<Status, DebuggerError>,
+        hits: &[usize],
+        watch_hits: &[(usize, Vec<u8>)],
+    ) {
+        self.selected_frame = 0;
+        match status {
+            Ok(Status::Exited(exit_code)) => {
+                println!("Child exit (status {})", exit_code);
+                self.inferior = None;
+            }
+            Ok(Status::Signaled(signal)) => {
+                println!("Child exit due to {}", signal);
+                self.crash = Some(signal);
+                self.inferior = None;
+            }
+            Ok(Status::Stopped(signal, rip)) => {
+                if is_crash_signal(signal) {
+                    self.crash = Some(signal);
+                }
+                let thread = self.inferior.as_ref().unwrap().current_thread();
+                if thread.id != self.reported_thread {
+                    println!("[Switching to thread {} (LWP {})]", thread.id, thread.tid);
+                    self.reported_thread = thread.id;
+                }
+                for bp in self.breakpoints.iter().filter(|bp| hits.contains(&bp.id)) {
+                    println!("Breakpoint {}, {}", bp.id, bp);
+                }
+                let inferior = self.inferior.as_ref().unwrap();
+                for (id, old_value) in watch_hits {
+                    let wp = self.watchpoints.iter().find(|wp| wp.id == *id).unwrap();
+                    let format = |value| Watchpoint::format_value(value, &wp.entity_type, &self.debug_data, inferior);
+                    println!("Hardware watchpoint {}: {}", wp.id, wp.expression);
+                    println!();
+                    println!("Old value = {}", format(old_value));
+                    println!("New value = {}", format(&wp.value));
+                }
+                match self.debug_data.get_line_from_addr(rip) {
+                    Some(val) => {
+                        println!("Child stopped (signal {}) at {}", signal, val);
+                        if let Some(text) = read_source_line(&val.file, val.number) {
+                            println!("{}\t{}", val.number, text);
+                        }
+                    }
+                    None => println!("Child stopped (signal {}) at {:#x}", signal, rip),
+                }
+                self.last_listed = None;
+                self.show_displays();
+                // get_next_command runs these before reading anything else
+                let bp_commands = self
+                    .breakpoints
+                    .iter()
+                    .filter(|bp| hits.contains(&bp.id))
+                    .flat_map(|bp| bp.commands.iter());
+                let wp_commands = self
+                    .watchpoints
+                    .iter()
+                    .filter(|wp| watch_hits.iter().any(|(id, _)| *id == wp.id))
+                    .flat_map(|wp| wp.commands.iter());
+                self.breakpoint_commands.extend(bp_commands.chain(wp_commands).cloned());
+            }
+            Err(DebuggerError::Ptrace(nix::Error::ESRCH)) => {
+                // The process died without deet seeing it exit, e.g. killed from outside
+                println!("Child is no longer running");
+                self.inferior = None;
+            }
+            Err(err) => println!("Error resuming child: {}", err),
+        }
+    }
+
+    /// Returns the source line the selected frame is executing.
+    fn current_line(&self) -> Option<Line> {
+        let frame = self.selected_frame().ok()?;
+        self.frame_line(&frame)
+    }
+
+    /// Returns the frames `bt` shows: the call stack up to and including `main`, with a frame
+    /// for each inlined call.
+    fn stack_frames(&self) -> Result<Vec<Frame>, String> {
+        let inferior = self
+            .inferior
+            .as_ref()
+            .ok_or_else(|| "No stack.".to_string())?;
+        let mut frames = Vec::new();
+        for frame in inferior.frames().map_err(|err| err.to_string())? {
+            let inlined_calls = self
+                .debug_data
+                .get_inline_frames(frame.lookup_addr())
+                .len()
+                .saturating_sub(1);
+            for inline_depth in 0..=inlined_calls {
+                frames.push(Frame {
+                    inline_depth,
+                    ..frame.clone()
+                });
+            }
+        }
+        let main = frames
+            .iter()
+            .position(|frame| self.frame_function(frame).as_deref() == Some("main"));
+        if let Some(main) = main {
+            frames.truncate(main + 1);
+        }
+        Ok(frames)
+    }
+
+    /// Returns the name of the function a frame stands for, which may be an inlined one.
+    fn frame_function(&self, frame: &Frame) -> Option<String> {
+        self.debug_data
+            .get_inline_frames(frame.lookup_addr())
+            .into_iter()
+            .nth(frame.inline_depth)?
+            .function
+    }
+
+    /// Returns the line a frame is executing. For a function that made an inlined call, this is
+    /// the line of the call.
+    fn frame_line(&self, frame: &Frame) -> Option<Line> {
+        self.debug_data
+            .get_inline_frames(frame.lookup_addr())
+            .into_iter()
+            .nth(frame.inline_depth)?
+            .line
+    }
+
+    /// Whether a frame is a call that was inlined into the function of the frame above it.
+    fn is_inlined(&self, frame: &Frame) -> bool {
+        frame.inline_depth + 1 < self.debug_data.get_inline_frames(frame.lookup_addr()).len()
+    }
+
+    fn selected_frame(&self) -> Result<Frame, String> {
+        self.stack_frames()?
+            .into_iter()
+            .nth(self.selected_frame)
+            .ok_or_else(|| "No stack.".to_string())
+    }
+
+    /// Describes a frame as "func file:line". Frames without debugging information show the
+    /// ELF symbol they are in, or `??`.
+    fn describe_frame(&self, frame: &Frame) -> String {
+        let addr = frame.lookup_addr();
+        let func = self
+            .frame_function(frame)
+            .or_else(|| self.inferior.as_ref()?.symbol_for_addr(addr))
+            .unwrap_or_else(|| "??".to_string());
+        match self.frame_line(frame) {
+            Some(line) => format!("{} {}", func, line),
+            None => format!("{} {:#x}", func, frame.pc),
+        }
+    }
+
+    /// Prints the call stack, marking the selected frame with `=>`.
+    fn print_backtrace(&self) {
+        let frames = match self.stack_frames() {
+            Ok(frames) => frames,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        for (level, frame) in frames.iter().enumerate() {
+            let marker = if level == self.selected_frame { "=>" } else { "  " };
+            println!("{} #{:<3}{}", marker, level, self.describe_frame(frame));
+        }
+        if frames.len() >= MAX_FRAMES {
+            println!("(More stack frames follow...)");
+        }
+    }
+
+    /// Selects frame `level` for printing variables and listing source, and shows it.
+    fn select_frame(&mut self, level: usize) {
+        let frames = match self.stack_frames() {
+            Ok(frames) => frames,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let frame = match frames.get(level) {
+            Some(frame) => frame,
+            None => {
+                println!("No frame at level {}.", level);
+                return;
+            }
+        };
+        self.selected_frame = level;
+        self.last_listed = None;
+        println!("#{:<3}{}", level, self.describe_frame(frame));
+        if let Some(line) = self.frame_line(frame) {
+            if let Some(text) = read_source_line(&line.file, line.number) {
+                println!("{}\t{}", line.number, text);
+            }
+        }
+    }
+
+    /// Moves the selected frame `count` frames towards the outermost frame (up) or the innermost
+    /// one (down).
+    fn move_frame(&mut self, up: bool, count: usize) {
+        let num_frames = match self.stack_frames() {
+            Ok(frames) => frames.len(),
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        if up && self.selected_frame + 1 >= num_frames {
+            println!("Initial frame selected; you cannot go up.");
+        } else if !up && self.selected_frame == 0 {
+            println!("Bottom (innermost) frame selected; you cannot go down.");
+        } else if up {
+            // Counts are clamped to the outermost and innermost frames, however large they are
+            self.select_frame(self.selected_frame.saturating_add(count).min(num_frames - 1));
+        } else {
+            self.select_frame(self.selected_frame.saturating_sub(count));
+        }
+    }
+
+    /// Works out which source file and line `list` should show, and whether that line goes in the
+    /// middle of the listing rather than at its start. Without an argument, `list` continues the
+    /// previous listing, or shows the lines around where the inferior is stopped (or around
+    /// `main`).
+    fn resolve_list_location(
+        &self,
+        arg: Option<&str>,
+    ) -> Result<(String, usize, bool), DebuggerError> {
+        let centered = |path: String, number: usize| (path, number, true);
+        let arg = match arg {
+            Some(arg) => arg,
+            None => {
+                if let Some((path, last_line)) = &self.last_listed {
+                    return Ok((path.clone(), last_line + 1, false));
+                }
+                let line = match self.current_line() {
+                    Some(line) => line,
+                    None => {
+                        let addr = self.debug_data.get_addr_for_function(None, "main")?;
+                        self.debug_data
+                            .get_line_from_addr(addr)
+                            .ok_or_else(|| {
+                                DebuggerError::Location("No line information for main".to_string())
+                            })?
+                    }
+                };
+                return Ok(centered(line.file, line.number));
+            }
+        };
+
+        let (file, location) = match arg.rsplit_once(':') {
+            Some((file, location)) => (Some(file), location),
+            None => (None, arg),
+        };
+        match location.parse::<usize>() {
+            Ok(number) => {
+                let path = match (file, self.current_line()) {
+                    (None, Some(line)) => line.file,
+                    (file, _) => self.debug_data.get_source_path(file)?,
+                };
+                Ok(centered(path, number))
+            }
+            Err(_) => {
+                let addr = self.debug_data.get_addr_for_function(file, location)?;
+                let line = self
+                    .debug_data
+                    .get_line_from_addr(addr)
+                    .ok_or_else(|| {
+                        DebuggerError::Location(format!("No line information for {}", location))
+                    })?;
+                Ok(centered(line.file, line.number))
+            }
+        }
+    }
+
+    /// Prints ten numbered lines of a source file, starting at `line` or with `line` in the middle
+    /// if `centered`. Marks the line the inferior is stopped at with `=>` and lines that have an
+    /// enabled breakpoint with `B`.
+    fn list_source(&mut self, path: String, line: usize, centered: bool) {
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                println!("Could not read source file {}: {}", path, err);
+                return;
+            }
+        };
+        let lines: Vec<&str> = source.lines().collect();
+        if line > lines.len() {
+            println!("Line number {} out of range; \"{}\" has {} lines.", line, path, lines.len());
+            return;
+        }
+        let first_line = if centered { line.saturating_sub(5).max(1) } else { line };
+        let last_line = (first_line + 9).min(lines.len());
+        let curr_line = self.current_line();
+        for number in first_line..=last_line {
+            let is_curr = curr_line
+                .as_ref()
+                .is_some_and(|line| line.file == path && line.number == number);
+            let has_breakpoint = self.breakpoints.iter().any(|bp| {
+                bp.enabled
+                    && bp.line.as_ref().is_some_and(|line| line.file == path && line.number == number)
+            });
+            println!(
+                "{}{} {:<4} {}",
+                if is_curr { "=>" } else { "  " },
+                if has_breakpoint { "B" } else { " " },
+                number,
+                lines[number - 1]
+            );
+        }
+        self.last_listed = Some((path, last_line));
+    }
+
+    /// Handles `x/NFU ADDR`. Without an address, continues after the memory shown last.
+    fn examine_memory(&mut self, spec: &str, addr: &str) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        let format = match ExamineFormat::parse(spec, self.examine_format) {
+            Ok(format) => format,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let addr = if addr.trim().is_empty() {
+            match self.next_examine_addr {
+                Some(addr) => addr,
+                None => {
+                    println!("Argument required (starting display address).");
+                    return;
+                }
+            }
+        } else {
+            match self.evaluate_address(addr) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            }
+        };
+        let next_addr = match format.format {
+            's' => self.examine_strings(addr, format.count),
+            'i' => self.examine_instructions(addr, format.count),
+            _ => self.examine_units(addr, format),
+        };
+        self.examine_format = format;
+        self.next_examine_addr = Some(next_addr);
+    }
+
+    /// Prints `format.count` units of memory starting at `addr`, and returns the address after
+    /// them.
+    fn examine_units(&self, addr: usize, format: ExamineFormat) -> usize {
+        let mut remaining = match format.count.checked_mul(format.unit) {
+            Some(len) => len,
+            None => {
+                println!("Too much memory to examine.");
+                return addr;
+            }
+        };
+        // Memory is read a line at a time, so that a large count doesn't need one huge buffer
+        let line_len = format.unit * format.units_per_line();
+        let mut line_addr = addr;
+        while remaining > 0 {
+            let len = remaining.min(line_len);
+            let line = match self.read_memory(line_addr, len) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    println!("Cannot access memory at address {:#x}: {}", line_addr, err);
+                    break;
+                }
+            };
+            let values: Vec<String> = line
+                .chunks(format.unit)
+                .map(|unit| match format.format {
+                    'a' => self.format_address(unit_value(unit) as usize),
+                    _ => format.format_unit(unit),
+                })
+                .collect();
+            println!("{}:\t{}", self.format_address(line_addr), values.join("\t"));
+            line_addr += len;
+            remaining -= len;
+        }
+        line_addr
+    }
+
+    /// Prints `count` NUL-terminated strings starting at `addr`, and returns the address after
+    /// them. Strings are cut off after 200 characters, like gdb does.
+    fn examine_strings(&self, mut addr: usize, count: usize) -> usize {
+        const MAX_STRING_LEN: usize = 200;
+        for _ in 0..count {
+            let (string, terminated) =
+                match read_c_string(addr, MAX_STRING_LEN, |addr, len| self.read_memory(addr, len)) {
+                    Ok(string) => string,
+                    Err(err) => {
+                        println!("Cannot access memory at address {:#x}: {}", addr, err);
+                        return addr;
+                    }
+                };
+            let ellipsis = if terminated { "" } else { "..." };
+            println!("{}:\t{}{}", self.format_address(addr), quote_string(&string), ellipsis);
+            addr += string.len() + terminated as usize;
+        }
+        addr
+    }
+
+    /// Prints `count` instructions starting at `addr`, and returns the address after them.
+    fn examine_instructions(&self, addr: usize, count: usize) -> usize {
+        let pc = self.inferior.as_ref().and_then(|inferior| inferior.get_rip().ok());
+        let mut next_addr = addr;
+        let mut remaining = count;
+        while remaining > 0 {
+            // The next page may not be mapped, so if reading that far fails, disassemble what is
+            // left of this page first
+            let page_end = (next_addr | 0xfff) + 1;
+            // Instructions are read a batch at a time, so that a large count doesn't need one
+            // huge buffer
+            let batch = remaining.min(INSTRUCTIONS_PER_READ);
+            let bytes = match self
+                .read_memory(next_addr, batch * MAX_INSTRUCTION_LEN)
+                .or_else(|_| self.read_memory(next_addr, page_end - next_addr))
+            {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    println!("Cannot access memory at address {:#x}: {}", next_addr, err);
+                    break;
+                }
+            };
+            let instructions = disassemble::decode(&bytes, next_addr, batch);
+            if instructions.is_empty() {
+                break;
+            }
+            for instruction in &instructions {
+                let marker = if Some(instruction.addr) == pc { "=> " } else { "   " };
+                println!(
+                    "{}{}:\t{}",
+                    marker,
+                    self.format_address(instruction.addr),
+                    self.format_instruction(instruction)
+                );
+                next_addr = instruction.addr + instruction.len;
+            }
+            remaining -= instructions.len();
+        }
+        next_addr
+    }
+
+    /// Disassembles a whole function, interleaved with its source lines. Without a name, the
+    /// function of the selected frame is used.
+    fn disassemble_function(&self, name: Option<&str>) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        let addr = match name {
+            Some(name) => {
+                let (file, name) = match name.rsplit_once(':') {
+                    Some((file, name)) => (Some(file), name),
+                    None => (None, name),
+                };
+                self.debug_data
+                    .get_addr_for_function(file, name)
+                    .map_err(|err| err.to_string())
+            }
+            None => self
+                .selected_frame()
+                .map(|frame| frame.lookup_addr()),
+        };
+        let func = match addr.map(|addr| self.debug_data.get_function_for_addr(addr)) {
+            Ok(Some(func)) => func,
+            Ok(None) => {
+                println!("No function contains program counter for selected frame.");
+                return;
+            }
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let bytes = match self.read_memory(func.address, func.text_length) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("Cannot access memory at address {:#x}: {}", func.address, err);
+                return;
+            }
+        };
+        let pc = self.selected_frame().ok().map(|frame| frame.pc);
+
+        println!("Dump of assembler code for function {}:", func.name);
+        let mut curr_line = None;
+        for instruction in disassemble::decode(&bytes, func.address, usize::MAX) {
+            if let Some(line) = self.debug_data.get_line_from_addr(instruction.addr) {
+                if curr_line.as_ref() != Some(&(line.file.clone(), line.number)) {
+                    match read_source_line(&line.file, line.number) {
+                        Some(text) => println!("{}\t{}", line.number, text),
+                        None => println!("{}\tin {}", line.number, line.file),
+                    }
+                    curr_line = Some((line.file, line.number));
+                }
+            }
+            let marker = if Some(instruction.addr) == pc { "=> " } else { "   " };
+            println!(
+                "{}{:#x} <+{}>:\t{}",
+                marker,
+                instruction.addr,
+                instruction.addr - func.address,
+                self.format_instruction(&instruction)
+            );
+        }
+        println!("End of assembler dump.");
+    }
+
+    /// Formats an instruction, labelling the target of direct jumps and calls.
+    fn format_instruction(&self, instruction: &Instruction) -> String {
+        match instruction.branch_target.and_then(|target| self.debug_data.get_symbol_for_addr(target)) {
+            Some((name, 0)) => format!("{} <{}>", instruction.text, name),
+            Some((name, offset)) => format!("{} <{}+{}>", instruction.text, name, offset),
+            None => instruction.text.clone(),
+        }
+    }
+
+    /// Formats an address, labelled with the function or global variable it is in.
+    fn format_address(&self, addr: usize) -> String {
+        match self.debug_data.get_symbol_for_addr(addr) {
+            Some((name, 0)) => format!("{:#x} <{}>", addr, name),
+            Some((name, offset)) => format!("{:#x} <{}+{}>", addr, name, offset),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    /// Reads the inferior's memory, showing the original instructions where breakpoints are
+    /// inserted.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, DebuggerError> {
+        let inferior = self.inferior.as_ref().ok_or(DebuggerError::NoProcess)?;
+        let mut bytes = inferior.read_bytes(addr, len)?;
+        if inferior.runs_other_program() {
+            return Ok(bytes);
+        }
+        for (bp_addr, orig_byte) in &self.break_list {
+            if (addr..addr + len).contains(bp_addr) {
+                bytes[bp_addr - addr] = *orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Evaluates the address given to `x`: a number, `$register`, `&variable`, a variable holding
+    /// an address, or a function name.
+    fn evaluate_address(&self, expr: &str) -> Result<usize, String> {
+        self.evaluate(expr)?.as_address(&self.debug_data)
+    }
+
+    /// Prints the named registers, or all of them, in hex and decimal.
+    fn print_registers(&self, names: &[String]) {
+        let inferior = match self.inferior.as_ref() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program has no registers now.");
+                return;
+            }
+        };
+        let mut regs = match inferior.get_registers() {
+            Ok(regs) => regs,
+            Err(err) => {
+                println!("Could not read registers: {}", err);
+                return;
+            }
+        };
+        let names: Vec<&str> = if names.is_empty() {
+            REGISTER_NAMES.to_vec()
+        } else {
+            names.iter().map(|name| name.trim_start_matches('$')).collect()
+        };
+        for name in names {
+            match register_field(&mut regs, name) {
+                Some(value) => println!("{:<15}{:<19}{}", name, format!("{:#x}", value), *value as i64),
+                None => println!("Invalid register \"{}\"", name),
+            }
+        }
+    }
+
+    /// Handles `set $name = value`.
+    fn set_register(&mut self, name: &str, value: &str) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program has no registers now.");
+                return;
+            }
+        };
+        let mut regs = match inferior.get_registers() {
+            Ok(regs) => regs,
+            Err(err) => {
+                println!("Could not read registers: {}", err);
+                return;
+            }
+        };
+        if register_field(&mut regs, name).is_none() {
+            println!("Invalid register \"{}\"", name);
+            return;
+        }
+        let value = match parse_integer(value) {
+            Some(value) => value,
+            None => {
+                println!("Invalid number \"{}\"", value);
+                return;
+            }
+        };
+        if let Err(err) = inferior.set_register(name, value) {
+            println!("Could not set register {}: {}", name, err);
+        }
+        // Changing %rip or %rsp changes the stack, so start from the innermost frame again
+        self.selected_frame = 0;
+        self.last_listed = None;
+    }
+
+    /// Handles `set var lvalue = expression`, converting the value to the type of the lvalue like
+    /// a C assignment does.
+    fn set_variable(&mut self, lhs: &str, rhs: &str) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        let assignment = Expression::parse(lhs, &self.debug_data).and_then(|lhs| {
+            let rhs = Expression::parse(rhs, &self.debug_data)?;
+            self.with_context(|ctx| {
+                let (addr, entity_type) = lhs.evaluate_lvalue(ctx)?;
+                let bytes = ctx.convert(&rhs.evaluate(ctx)?, &entity_type)?;
+                Ok((addr, bytes))
+            })
+        });
+        match assignment {
+            Ok((addr, bytes)) => {
+                if let Err(err) = self.write_memory(addr, &bytes) {
+                    println!("Cannot access memory at address {:#x}: {}", addr, err);
+                }
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// Handles `set *ADDR = bytes`, where bytes is a list of byte values such as `0x90 0x90`.
+    /// Negative values from -128 are taken as signed bytes, so -1 writes 0xff.
+    fn set_memory(&mut self, addr: &str, value: &str) {
+        let addr = match self.evaluate_address(addr) {
+            Ok(addr) => addr,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let bytes: Option<Vec<u8>> = value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|byte| !byte.is_empty())
+            .map(parse_byte)
+            .collect();
+        match bytes {
+            Some(bytes) if !bytes.is_empty() => {
+                if let Err(err) = self.write_memory(addr, &bytes) {
+                    println!("Cannot access memory at address {:#x}: {}", addr, err);
+                }
+            }
+            _ => println!(
+                "Invalid bytes \"{}\"; expected values from -128 to 255 (0xff)",
+                value
+            ),
+        }
+    }
+
+    /// Writes to the inferior's memory. Where a breakpoint is inserted, the new byte is saved as
+    /// the original instruction and the 0xcc stays in place.
+    fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), DebuggerError> {
+        let inferior = self.inferior.as_mut().ok_or(DebuggerError::NoProcess)?;
+        let mut bytes = bytes.to_vec();
+        for (byte_addr, byte) in (addr..).zip(bytes.iter_mut()) {
+            if let Some(orig_byte) = self.break_list.get_mut(&byte_addr) {
+                *orig_byte = *byte;
+                *byte = 0xcc;
+            }
+        }
+        inferior.write_bytes(addr, &bytes)?;
+        // Our own writes shouldn't be reported as changes by the watchpoints
+        for wp in self.watchpoints.iter_mut().filter(|wp| wp.slot.is_some()) {
+            wp.update_value(inferior);
+        }
+        Ok(())
+    }
+
+    /// Prints the locals in scope in the selected frame. Variables shadowed by an inner block are
+    /// left out, since their names refer to the inner ones.
+    fn print_locals(&self) {
+        let frame = match self.selected_frame() {
+            Ok(frame) => frame,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let locals = self
+            .debug_data
+            .get_visible_variables(frame.lookup_addr(), frame.inline_depth);
+        if locals.is_empty() {
+            println!("No locals.");
+            return;
+        }
+        for (i, var) in locals.iter().enumerate() {
+            if !locals[i + 1..].iter().any(|inner| inner.name == var.name) {
+                self.print_expression(&var.name);
+            }
+        }
+    }
+
+    /// Handles `print EXPRESSION`.
+    fn print_expression(&self, text: &str) {
+        match self.evaluate(text) {
+            Ok(value) => println!("{} = {}", text.trim(), self.format_top_level_value(&value)),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// Formats a value the way `print` shows it. Like gdb, pointers other than strings are
+    /// labelled with their type.
+    fn format_top_level_value(&self, value: &Value) -> String {
+        if value.optimized_out {
+            return "<optimized out>".to_string();
+        }
+        let formatted = self.format_value(&value.ty, &value.bytes);
+        let is_string = match self.debug_data.resolve_type(&value.ty).kind {
+            TypeKind::Pointer(Some(target)) => self
+                .debug_data
+                .get_type(target)
+                .is_some_and(|target| self.debug_data.resolve_type(target).is_char()),
+            TypeKind::Pointer(None) => false,
+            _ => return formatted,
+        };
+        if is_string {
+            formatted
+        } else {
+            format!("({}) {}", value.ty.name, formatted)
+        }
+    }
+
+    /// Formats a value, reading any string it points to from the inferior.
+    fn format_value(&self, entity_type: &Type, bytes: &[u8]) -> String {
+        self.debug_data
+            .format_value(entity_type, bytes, &|addr, len| self.read_memory(addr, len).ok())
+    }
+
+    /// Evaluates an expression in the selected frame, or with only globals in scope if the
+    /// program isn't running.
+    fn evaluate(&self, text: &str) -> Result<Value, String> {
+        let expression = Expression::parse(text, &self.debug_data)?;
+        self.with_context(|ctx| expression.evaluate(ctx))
+    }
+
+    fn with_context<T>(&self, f: impl FnOnce(&Context) -> Result<T, String>) -> Result<T, String> {
+        let frame = match self.inferior {
+            Some(_) => Some(self.selected_frame()?),
+            None => None,
+        };
+        f(&Context {
+            debug_data: &self.debug_data,
+            inferior: self.inferior.as_ref(),
+            frame: frame.as_ref(),
+        })
+    }
+
+    /// Handles `display EXPRESSION`, showing it right away if the program is running.
+    fn add_display(&mut self, text: &str) {
+        let expression = match Expression::parse(text, &self.debug_data) {
+            Ok(expression) => expression,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let display = AutoDisplay {
+            id: self.next_display_id,
+            expression,
+        };
+        self.next_display_id += 1;
+        if self.inferior.is_some() {
+            self.show_display(&display);
+        }
+        self.displays.push(display);
+    }
+
+    fn show_displays(&self) {
+        if self.inferior.is_none() {
+            return;
+        }
+        for display in &self.displays {
+            self.show_display(display);
+        }
+    }
+
+    fn show_display(&self, display: &AutoDisplay) {
+        match self.with_context(|ctx| display.expression.evaluate(ctx)) {
+            Ok(value) => println!(
+                "{}: {} = {}",
+                display.id,
+                display.expression,
+                self.format_top_level_value(&value)
+            ),
+            Err(err) => println!("{}: {} = <error: {}>", display.id, display.expression, err),
+        }
+    }
+
+    fn info_display(&self) {
+        if self.displays.is_empty() {
+            println!("There are no auto-display expressions now.");
+            return;
+        }
+        println!("Auto-display expressions now in effect:");
+        println!("Num Expression");
+        for display in &self.displays {
+            println!("{:<4}{}", format!("{}:", display.id), display.expression);
+        }
+    }
+
+    /// Parses a location: `*ADDR`, `LINE`, `FUNCTION`, `FILE:LINE` or `FILE:FUNCTION`. A line
+    /// without a file refers to the file the inferior is stopped in, and a function refers to the
+    /// end of its prologue.
+    fn parse_address(&self, addr: &str) -> Result<usize, DebuggerError> {
+        if let Some(addr) = addr.strip_prefix('*') {
+            let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
+                &addr[2..]
             } else {
-                &addr[1..]
+                addr
             };
-            // println!("{} {}", addr, addr_without_0x);
-            usize::from_str_radix(addr_without_0x, 16).ok()
+            usize::from_str_radix(addr_without_0x, 16)
+                .map_err(|_| DebuggerError::Location(format!("Invalid address \"{}\"", addr)))
         } else {
-            match addr.to_string().parse() {
-                Ok(val) => self.debug_data.get_addr_for_line(None, val),
-                Err(_)  => self.debug_data.get_addr_for_function(None, addr),
+            let (file, location) = match addr.rsplit_once(':') {
+                Some((file, location)) => (Some(file), location),
+                None => (None, addr),
+            };
+            match location.parse() {
+                Ok(line) => {
+                    let curr_file = self
+                        .selected_frame()
+                        .ok()
+                        .and_then(|frame| self.debug_data.get_file_for_addr(frame.lookup_addr()));
+                    self.debug_data.get_addr_for_line(file.or(curr_file), line)
+                }
+                Err(_) => {
+                    // Stop after the prologue, once the arguments are in their stack slots
+                    let addr = self.debug_data.get_addr_for_function(file, location)?;
+                    Ok(match self.debug_data.get_function_for_addr(addr) {
+                        Some(func) => self.debug_data.get_addr_after_prologue(func),
+                        None => addr,
+                    })
+                }
             }
         }
     }
 
     /// This function prompts the user to enter a command, and continues re-prompting until the user
     /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
+    /// Breakpoint commands and lines from scripts are taken first.
     fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            let line = match self.next_line("(deet) ") {
+                Some(line) => line,
+                None => return DebuggerCommand::Quit,
+            };
+            // Scripts may have blank lines and comments
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
+                return cmd;
+            } else {
+                println!("Unrecognized command \"{}\".", line.trim());
+            }
+        }
+    }
+
+    /// Returns the next line of input: the next breakpoint command or script line, or else a line
+    /// typed at `prompt`.
+    /// Returns None when there is no more input, which means quitting.
+    fn next_line(&mut self, prompt: &str) -> Option<String> {
+        if let Some(line) = self.breakpoint_commands.pop_front() {
+            return Some(line);
+        }
+        if let Some(line) = self.script_lines.pop_front() {
+            return Some(line);
+        }
+        if self.batch {
+            return None;
+        }
         loop {
             // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
+            match self.readline.readline(prompt) {
                 Err(ReadlineError::Interrupted) => {
                     // User pressed ctrl+c. We're going to ignore it
                     println!("Type \"quit\" to exit");
                 }
                 Err(ReadlineError::Eof) => {
                     // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+                    return None;
                 }
                 Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
+                    // The terminal is gone; there is no way to read more commands
+                    println!("Error reading command: {}", err);
+                    return None;
                 }
                 Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
-                    }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
+                    if !line.trim().is_empty() {
+                        let _ = self.readline.add_history_entry(line.as_str());
+                        if let Err(err) = self.readline.save_history(&self.history_path) {
+                            println!(
+                                "Warning: failed to save history file at {}: {}",
+                                self.history_path, err
+                            );
+                        }
                     }
+                    return Some(line);
                 }
             }
         }
     }
 }
+
+/// Reads line `number` (counting from 1) of a source file.
+fn read_source_line(path: &str, number: usize) -> Option<String> {
+    let source = fs::read_to_string(path).ok()?;
+    source.lines().nth(number.checked_sub(1)?).map(|line| line.to_string())
+}
+
+/// Parses a decimal or `0x` hexadecimal integer. Negative numbers are stored in two's complement.
+fn parse_integer(text: &str) -> Option<u64> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if text.starts_with('-') {
+        text.parse::<i64>().ok().map(|value| value as u64)
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Parses a byte value for `set *ADDR`: 0 to 255, or -128 to -1 for a signed byte.
+fn parse_byte(text: &str) -> Option<u8> {
+    if text.starts_with('-') {
+        let value = i8::try_from(text.parse::<i64>().ok()?).ok()?;
+        Some(value as u8)
+    } else {
+        u8::try_from(parse_integer(text)?).ok()
+    }
+}
+
+/// Reads the lines of a command script.
+fn read_script(path: &str) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?.lines().map(String::from).collect())
+}
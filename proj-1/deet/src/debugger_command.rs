@@ -4,10 +4,54 @@ pub enum DebuggerCommand {
     Cont,
     Back,
     Break(Vec<String>),
+    /// The expression to print, or an empty string for every local
+    Print(String),
+    Step,
+    Next,
+    StepInstruction(Option<String>),
+    NextInstruction(Option<String>),
+    Finish,
+    InfoBreakpoints,
+    Delete(Vec<String>),
+    Disable(Vec<String>),
+    Enable(Vec<String>),
+    Ignore(Vec<String>),
+    Watch(String),
+    List(Option<String>),
+    Attach(String),
+    Detach,
+    Up(Option<String>),
+    Down(Option<String>),
+    Frame(Option<String>),
+    InfoRegisters(Vec<String>),
+    Set(String),
+    /// `x/NFU ADDR`: the NFU spec and the address expression, either of which may be empty
+    Examine(String, String),
+    Disassemble(Option<String>),
+    Display(String),
+    Undisplay(Vec<String>),
+    InfoDisplay,
+    InfoLocals,
+    /// `inferior [N]`: the number of the inferior to switch to
+    Inferior(Option<String>),
+    InfoInferiors,
+    /// `thread [N]`: the number of the thread to switch to
+    Thread(Option<String>),
+    InfoThreads,
+    /// `handle SIGNAL... ACTION...`
+    Handle(Vec<String>),
+    /// `signal SIG`: continue, delivering SIG
+    Signal(String),
+    /// `info signals [SIG]`
+    InfoSignals(Option<String>),
+    /// `source FILE`: run the commands in FILE
+    Source(String),
+    /// `commands [N]`: the breakpoint whose command list is read next; the last one set if None
+    Commands(Option<String>),
 }
 
 impl DebuggerCommand {
-    pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
+    pub fn from_tokens(tokens: &[&str]) -> Option<DebuggerCommand> {
         match tokens[0] {
             "q" | "quit" => Some(DebuggerCommand::Quit),
             "r" | "run" => {
@@ -31,6 +75,67 @@ impl DebuggerCommand {
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             }
+            "p" | "print" => Some(DebuggerCommand::Print(tokens[1..].join(" "))),
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "si" | "stepi" => Some(DebuggerCommand::StepInstruction(tokens.get(1).map(|s| s.to_string()))),
+            "ni" | "nexti" => Some(DebuggerCommand::NextInstruction(tokens.get(1).map(|s| s.to_string()))),
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "i" | "info" => match tokens.get(1).copied() {
+                Some("b") | Some("break") | Some("breakpoints") => {
+                    Some(DebuggerCommand::InfoBreakpoints)
+                }
+                Some("r") | Some("registers") => Some(DebuggerCommand::InfoRegisters(
+                    tokens[2..].iter().map(|s| s.to_string()).collect(),
+                )),
+                Some("display") => Some(DebuggerCommand::InfoDisplay),
+                Some("locals") => Some(DebuggerCommand::InfoLocals),
+                Some("inferiors") => Some(DebuggerCommand::InfoInferiors),
+                Some("threads") => Some(DebuggerCommand::InfoThreads),
+                Some("signals") | Some("handle") => {
+                    Some(DebuggerCommand::InfoSignals(tokens.get(2).map(|s| s.to_string())))
+                }
+                _ => None,
+            },
+            "d" | "delete" => Some(DebuggerCommand::Delete(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "disable" => Some(DebuggerCommand::Disable(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "enable" => Some(DebuggerCommand::Enable(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "ignore" => Some(DebuggerCommand::Ignore(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "watch" if tokens.len() > 1 => Some(DebuggerCommand::Watch(tokens[1..].join(" "))),
+            "l" | "list" => Some(DebuggerCommand::List(tokens.get(1).map(|s| s.to_string()))),
+            "attach" if tokens.len() == 2 => Some(DebuggerCommand::Attach(tokens[1].to_string())),
+            "detach" => Some(DebuggerCommand::Detach),
+            "up" => Some(DebuggerCommand::Up(tokens.get(1).map(|s| s.to_string()))),
+            "down" => Some(DebuggerCommand::Down(tokens.get(1).map(|s| s.to_string()))),
+            "f" | "frame" => Some(DebuggerCommand::Frame(tokens.get(1).map(|s| s.to_string()))),
+            "inferior" => Some(DebuggerCommand::Inferior(tokens.get(1).map(|s| s.to_string()))),
+            "thread" => Some(DebuggerCommand::Thread(tokens.get(1).map(|s| s.to_string()))),
+            "handle" if tokens.len() > 1 => Some(DebuggerCommand::Handle(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "signal" if tokens.len() == 2 => Some(DebuggerCommand::Signal(tokens[1].to_string())),
+            "commands" => Some(DebuggerCommand::Commands(tokens.get(1).map(|s| s.to_string()))),
+            "source" if tokens.len() == 2 => Some(DebuggerCommand::Source(tokens[1].to_string())),
+            "set" if tokens.len() > 1 => Some(DebuggerCommand::Set(tokens[1..].join(" "))),
+            x if x == "x" || x.starts_with("x/") => Some(DebuggerCommand::Examine(
+                x[1..].trim_start_matches('/').to_string(),
+                tokens[1..].join(" "),
+            )),
+            "disas" | "disassemble" => {
+                Some(DebuggerCommand::Disassemble(tokens.get(1).map(|s| s.to_string())))
+            }
+            "display" => Some(DebuggerCommand::Display(tokens[1..].join(" "))),
+            "undisplay" => Some(DebuggerCommand::Undisplay(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
             // Default case:
             _ => None,
         }
@@ -0,0 +1,49 @@
+//! x86-64 disassembly using iced-x86, printed in AT&T syntax like gdb does.
+
+use iced_x86::{Decoder, DecoderError, DecoderOptions, Formatter, GasFormatter, OpKind};
+
+/// Longest possible x86 instruction, in bytes
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+pub struct Instruction {
+    pub addr: usize,
+    pub len: usize,
+    /// Mnemonic and operands, e.g. "mov    %rsp,%rbp"
+    pub text: String,
+    /// Target of a direct jump or call, which callers can label with a symbol
+    pub branch_target: Option<usize>,
+}
+
+/// Decodes up to `max_count` instructions from `bytes`, which were read from address `addr`. An
+/// instruction cut off by the end of `bytes` is left out.
+pub fn decode(bytes: &[u8], addr: usize, max_count: usize) -> Vec<Instruction> {
+    let mut decoder = Decoder::with_ip(64, bytes, addr as u64, DecoderOptions::NONE);
+    let mut formatter = GasFormatter::new();
+    formatter.options_mut().set_first_operand_char_index(7);
+    formatter.options_mut().set_branch_leading_zeros(false);
+    formatter.options_mut().set_uppercase_hex(false);
+    let mut instructions = Vec::new();
+    while decoder.can_decode() && instructions.len() < max_count {
+        let instruction = decoder.decode();
+        if decoder.last_error() == DecoderError::NoMoreBytes {
+            break;
+        }
+        let mut text = String::new();
+        if instruction.is_invalid() {
+            text.push_str("(bad)");
+        } else {
+            formatter.format(&instruction, &mut text);
+        }
+        let branch_target = match instruction.op0_kind() {
+            OpKind::NearBranch64 => Some(instruction.near_branch_target() as usize),
+            _ => None,
+        };
+        instructions.push(Instruction {
+            addr: instruction.ip() as usize,
+            len: instruction.len(),
+            text,
+            branch_target,
+        });
+    }
+    instructions
+}
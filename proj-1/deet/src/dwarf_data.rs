@@ -1,10 +1,22 @@
+use crate::error::DebuggerError;
 use crate::gimli_wrapper;
 use addr2line::Context;
 use object::Object;
+use crate::examine::{quote_char, quote_string, read_c_string};
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::mem::size_of;
 use std::{fmt, fs};
 
+/// Bound on how deeply values are expanded, in case of malformed DWARF with cyclic types
+const MAX_TYPE_DEPTH: usize = 32;
+/// Longest string printed for a `char *` value
+const MAX_STRING_LEN: usize = 200;
+/// Most elements of an array that are printed
+const MAX_ARRAY_ELEMENTS: usize = 200;
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     ErrorOpeningFile,
     DwarfFormatError(gimli_wrapper::Error),
@@ -12,6 +24,7 @@ pub enum Error {
 
 pub struct DwarfData {
     files: Vec<File>,
+    types: HashMap<TypeId, Type>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
 }
 
@@ -32,61 +45,239 @@ impl DwarfData {
         let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
         let mmap = unsafe { memmap2::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
         let object = object::File::parse(&*mmap)
-            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+            .map_err(|e| gimli_wrapper::Error::ObjectError(e.to_string()))?;
         let endian = if object.is_little_endian() {
             gimli::RunTimeEndian::Little
         } else {
             gimli::RunTimeEndian::Big
         };
+        let (files, types) = gimli_wrapper::load_file(&object, endian)?;
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            files,
+            types,
+            addr2line: Context::new(&object).map_err(gimli_wrapper::Error::from)?,
         })
     }
 
-    #[allow(dead_code)]
-    fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
-        })
+    /// Finds the compilation unit for a source file. Leading directories may be left out as long
+    /// as the name is still unambiguous.
+    fn get_target_file(&self, file: &str) -> Result<&File, DebuggerError> {
+        if let Some(f) = self.files.iter().find(|f| f.name == file) {
+            return Ok(f);
+        }
+        let matches: Vec<&File> = self
+            .files
+            .iter()
+            .filter(|f| f.name.ends_with(&format!("/{}", file)))
+            .collect();
+        match matches.as_slice() {
+            [] => Err(DebuggerError::Location(format!("No source file named {}.", file))),
+            [f] => Ok(f),
+            _ => {
+                let names: Vec<&str> = matches.iter().map(|f| f.name.as_str()).collect();
+                Err(DebuggerError::Location(format!(
+                    "Ambiguous file name \"{}\": {}",
+                    file,
+                    names.join(", ")
+                )))
+            }
+        }
+    }
+
+    /// The file that defines `main`, which is where lines are looked up when no file is given.
+    fn get_default_file(&self) -> Result<&File, DebuggerError> {
+        self.files
+            .iter()
+            .find(|f| f.functions.iter().any(|func| func.name == "main"))
+            .or_else(|| self.files.first())
+            .ok_or_else(|| DebuggerError::Location("No symbol table is loaded.".to_string()))
     }
 
+    /// Returns the path to read a source file from. Without a file, the file that defines `main`
+    /// is used.
+    pub fn get_source_path(&self, file: Option<&str>) -> Result<String, DebuggerError> {
+        let target_file = match file {
+            Some(filename) => self.get_target_file(filename)?,
+            None => self.get_default_file()?,
+        };
+        Ok(target_file.path.clone())
+    }
+
+    /// Returns the address of the first instruction of `line_number`, or of the next line that
+    /// has code if it has none. Without a file, the file that defines `main` is used.
     #[allow(dead_code)]
-    pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
+    pub fn get_addr_for_line(
+        &self,
+        file: Option<&str>,
+        line_number: usize,
+    ) -> Result<usize, DebuggerError> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            None => self.get_default_file()?,
         };
-        Some(
-            target_file
-                .lines
-                .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+        target_file
+            .lines
+            .iter()
+            .filter(|line| line.number >= line_number)
+            .min_by_key(|line| (line.number, line.address))
+            .map(|line| line.address)
+            .ok_or_else(|| {
+                DebuggerError::Location(format!(
+                    "Line {} is out of range for \"{}\".",
+                    line_number, target_file.name
+                ))
+            })
     }
 
     #[allow(dead_code)]
-    pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
+    pub fn get_addr_for_function(
+        &self,
+        file: Option<&str>,
+        func_name: &str,
+    ) -> Result<usize, DebuggerError> {
         match file {
-            Some(filename) => Some(
-                self.get_target_file(filename)?
-                    .functions
-                    .iter()
-                    .find(|func| func.name == func_name)?
-                    .address,
-            ),
+            Some(filename) => Ok(self
+                .get_target_file(filename)?
+                .functions
+                .iter()
+                .find(|func| func.name == func_name)
+                .ok_or_else(|| {
+                    DebuggerError::Location(format!(
+                        "Function \"{}\" not defined in \"{}\".",
+                        func_name, filename
+                    ))
+                })?
+                .address),
             None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
+                let defining_files: Vec<&File> = self
+                    .files
+                    .iter()
+                    .filter(|file| file.functions.iter().any(|func| func.name == func_name))
+                    .collect();
+                match defining_files.as_slice() {
+                    [] => Err(DebuggerError::Location(format!(
+                        "Function \"{}\" not defined.",
+                        func_name
+                    ))),
+                    [file] => self.get_addr_for_function(Some(&file.name), func_name),
+                    _ => {
+                        let names: Vec<&str> =
+                            defining_files.iter().map(|f| f.name.as_str()).collect();
+                        Err(DebuggerError::Location(format!(
+                            "Function \"{}\" is defined in several files ({}); use FILE:{}",
+                            func_name,
+                            names.join(", "),
+                            func_name
+                        )))
                     }
                 }
-                None
             }
         }
     }
 
+    /// Returns the name of the compilation unit whose functions contain `curr_addr`.
+    #[allow(dead_code)]
+    pub fn get_file_for_addr(&self, curr_addr: usize) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|file| {
+                file.functions.iter().any(|func| {
+                    func.address <= curr_addr && curr_addr < func.address + func.text_length
+                })
+            })
+            .map(|file| file.name.as_str())
+    }
+
+    #[allow(dead_code)]
+    pub fn get_function_for_addr(&self, curr_addr: usize) -> Option<&Function> {
+        self.files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.address <= curr_addr && curr_addr < func.address + func.text_length)
+    }
+
+    /// Returns true if `addr` is the first instruction of a row in the line table.
+    #[allow(dead_code)]
+    pub fn is_line_start(&self, addr: usize) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.lines.iter().any(|line| line.address == addr))
+    }
+
+    /// Returns the address of the first line of a function's body, skipping the prologue that
+    /// sets up its stack frame.
+    #[allow(dead_code)]
+    pub fn get_addr_after_prologue(&self, func: &Function) -> usize {
+        self.files
+            .iter()
+            .flat_map(|file| file.lines.iter())
+            .map(|line| line.address)
+            .filter(|&addr| addr > func.address && addr < func.address + func.text_length)
+            .min()
+            .unwrap_or(func.address)
+    }
+
+    /// Returns the variables in scope at `curr_addr`, outermost first, in the function that is
+    /// `inline_depth` inlined calls out from the innermost one executing there.
+    pub fn get_visible_variables(&self, curr_addr: usize, inline_depth: usize) -> Vec<&Variable> {
+        let func = match self.get_function_for_addr(curr_addr) {
+            Some(func) => func,
+            None => return Vec::new(),
+        };
+        // Scopes containing the address, outermost first. Each inlined call starts a new frame.
+        let mut frames: Vec<Vec<&Variable>> = vec![func.variables.iter().collect()];
+        let mut scopes = &func.scopes;
+        while let Some(scope) = scopes.iter().find(|scope| scope.contains(curr_addr)) {
+            if scope.inlined_function.is_some() {
+                frames.push(Vec::new());
+            }
+            frames.last_mut().unwrap().extend(scope.variables.iter());
+            scopes = &scope.scopes;
+        }
+        let index = frames.len().saturating_sub(inline_depth + 1);
+        frames.swap_remove(index)
+    }
+
+    /// Looks up a variable by name. Locals in scope at `curr_addr` shadow globals, and locals of
+    /// inner blocks shadow those of outer ones.
+    #[allow(dead_code)]
+    pub fn get_variable(
+        &self,
+        curr_addr: usize,
+        inline_depth: usize,
+        name: &str,
+    ) -> Option<&Variable> {
+        if let Some(var) = self
+            .get_visible_variables(curr_addr, inline_depth)
+            .into_iter()
+            .rev()
+            .find(|var| var.name == name)
+        {
+            return Some(var);
+        }
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
+    /// Returns the function or global variable that contains `addr`, along with the offset of
+    /// `addr` into it, for labelling addresses like `<main+4>`.
+    pub fn get_symbol_for_addr(&self, addr: usize) -> Option<(&str, usize)> {
+        if let Some(func) = self.get_function_for_addr(addr) {
+            return Some((&func.name, addr - func.address));
+        }
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find_map(|var| match var.location {
+                Location::Address(start) if start <= addr && addr < start + var.entity_type.size.max(1) => {
+                    Some((var.name.as_str(), addr - start))
+                }
+                _ => None,
+            })
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
@@ -111,6 +302,217 @@ impl DwarfData {
         Some(frame.function?.raw_name().ok()?.to_string())
     }
 
+    /// Returns the functions executing at `curr_addr`, innermost first: the functions inlined
+    /// there, then the function containing it. Each comes with the line it is at, which for the
+    /// callers of inlined functions is the line of the inlined call.
+    pub fn get_inline_frames(&self, curr_addr: usize) -> Vec<InlineFrame> {
+        let mut inline_frames = Vec::new();
+        let mut frames = match self.addr2line.find_frames(curr_addr as u64) {
+            Ok(frames) => frames,
+            Err(_) => return inline_frames,
+        };
+        while let Ok(Some(frame)) = frames.next() {
+            let function = frame
+                .function
+                .and_then(|function| Some(function.raw_name().ok()?.to_string()));
+            let line = frame.location.and_then(|location| {
+                Some(Line {
+                    file: location.file?.to_string(),
+                    number: location.line? as usize,
+                    address: curr_addr,
+                })
+            });
+            inline_frames.push(InlineFrame { function, line });
+        }
+        inline_frames
+    }
+
+    pub fn get_type(&self, id: TypeId) -> Option<&Type> {
+        self.types.get(&id)
+    }
+
+    /// Finds a type by the name C spells it with, e.g. "unsigned int" or "struct node". Complete
+    /// definitions of structs win over forward declarations.
+    pub fn find_type(&self, name: &str) -> Option<&Type> {
+        self.types
+            .values()
+            .filter(|ty| ty.name == name)
+            .max_by_key(|ty| ty.size)
+    }
+
+    /// Returns the type of a pointer to `target`, reusing the program's own pointer type if it
+    /// has one.
+    pub fn pointer_to(&self, target: &Type) -> Type {
+        if let Some(id) = target.id {
+            let existing = self
+                .types
+                .values()
+                .find(|ty| matches!(ty.kind, TypeKind::Pointer(Some(pointee)) if pointee == id));
+            if let Some(existing) = existing {
+                return existing.clone();
+            }
+        }
+        let name = match target.name.find(" [") {
+            Some(index) => format!("{} (*){}", &target.name[..index], &target.name[index + 1..]),
+            None if target.name.ends_with('*') => format!("{}*", target.name),
+            None => format!("{} *", target.name),
+        };
+        Type {
+            name,
+            size: size_of::<usize>(),
+            kind: TypeKind::Pointer(target.id),
+            id: None,
+        }
+    }
+
+    /// Looks up an enumerator of any enum, returning its value and the enum type.
+    pub fn get_enumerator(&self, name: &str) -> Option<(i64, &Type)> {
+        self.types.values().find_map(|ty| match &ty.kind {
+            TypeKind::Enum(enumerators) => enumerators
+                .iter()
+                .find(|(enumerator, _)| enumerator == name)
+                .map(|(_, value)| (*value, ty)),
+            _ => None,
+        })
+    }
+
+    /// Looks through typedefs and qualifiers to the type that determines how a value is laid out.
+    pub fn resolve_type<'a>(&'a self, mut ty: &'a Type) -> &'a Type {
+        // Bounded, in case of malformed DWARF with a cycle of typedefs
+        for _ in 0..MAX_TYPE_DEPTH {
+            match ty.kind {
+                TypeKind::Typedef(Some(target)) | TypeKind::Qualified(_, Some(target)) => {
+                    match self.types.get(&target) {
+                        Some(target) => ty = target,
+                        None => break,
+                    }
+                }
+                _ => break,
+            }
+        }
+        ty
+    }
+
+    /// Finds a field of a struct or union, including fields of anonymous members. Returns the
+    /// field's offset into the value and its type.
+    pub fn get_member(&self, ty: &Type, name: &str) -> Option<(usize, &Type)> {
+        let members = match &self.resolve_type(ty).kind {
+            TypeKind::Struct(members) | TypeKind::Union(members) => members,
+            _ => return None,
+        };
+        members.iter().find_map(|member| {
+            let member_type = self.types.get(&member.type_id)?;
+            if member.name == name {
+                Some((member.offset, member_type))
+            } else if member.name.is_empty() {
+                let (offset, ty) = self.get_member(member_type, name)?;
+                Some((member.offset + offset, ty))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Formats the raw (little-endian) bytes of a value the way gdb prints it. Strings that
+    /// `char *` values point to are read with `read_memory(addr, len)`.
+    pub fn format_value(
+        &self,
+        ty: &Type,
+        bytes: &[u8],
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+    ) -> String {
+        self.format_nested_value(ty, bytes, read_memory, 0)
+    }
+
+    fn format_nested_value(
+        &self,
+        ty: &Type,
+        bytes: &[u8],
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+        depth: usize,
+    ) -> String {
+        let ty = self.resolve_type(ty);
+        if depth > MAX_TYPE_DEPTH {
+            return "{...}".to_string();
+        }
+        match &ty.kind {
+            TypeKind::Struct(members) | TypeKind::Union(members) => {
+                let fields: Vec<String> = members
+                    .iter()
+                    .map(|member| {
+                        let value = match self.types.get(&member.type_id) {
+                            Some(member_type) => {
+                                match bytes.get(member.offset..member.offset + member_type.size) {
+                                    Some(field) => self.format_nested_value(member_type, field, read_memory, depth + 1),
+                                    None => "<unreadable>".to_string(),
+                                }
+                            }
+                            None => "<unknown type>".to_string(),
+                        };
+                        if member.name.is_empty() {
+                            value
+                        } else {
+                            format!("{} = {}", member.name, value)
+                        }
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            TypeKind::Array { element, .. } => {
+                let element = match self.types.get(element) {
+                    Some(element) => self.resolve_type(element),
+                    None => return "<unknown type>".to_string(),
+                };
+                if element.is_char() {
+                    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+                    return quote_string(&bytes[..end]);
+                }
+                if element.size == 0 {
+                    return "{}".to_string();
+                }
+                let values: Vec<String> = bytes
+                    .chunks_exact(element.size)
+                    .take(MAX_ARRAY_ELEMENTS)
+                    .map(|value| self.format_nested_value(element, value, read_memory, depth + 1))
+                    .collect();
+                let ellipsis = if bytes.len() / element.size > MAX_ARRAY_ELEMENTS { "..." } else { "" };
+                format!("{{{}{}}}", values.join(", "), ellipsis)
+            }
+            TypeKind::Pointer(target) if bytes.len() == size_of::<usize>() => {
+                let addr = raw_value(bytes) as usize;
+                let target = target.and_then(|target| self.types.get(&target)).map(|target| self.resolve_type(target));
+                match target {
+                    Some(target) if target.is_char() && addr != 0 => {
+                        let read = |addr, len| read_memory(addr, len).ok_or(());
+                        match read_c_string(addr, MAX_STRING_LEN, read) {
+                            Ok((string, terminated)) => {
+                                let ellipsis = if terminated { "" } else { "..." };
+                                format!("{:#x} {}{}", addr, quote_string(&string), ellipsis)
+                            }
+                            Err(()) => format!("{:#x} <error: Cannot access memory at address {:#x}>", addr, addr),
+                        }
+                    }
+                    Some(Type {
+                        kind: TypeKind::Function(_),
+                        ..
+                    }) => match self.get_symbol_for_addr(addr) {
+                        Some((name, 0)) => format!("{:#x} <{}>", addr, name),
+                        _ => format!("{:#x}", addr),
+                    },
+                    _ => format!("{:#x}", addr),
+                }
+            }
+            TypeKind::Enum(enumerators) if !bytes.is_empty() && bytes.len() <= size_of::<u64>() => {
+                let value = sign_extend(raw_value(bytes), bytes.len());
+                match enumerators.iter().find(|(_, enumerator)| *enumerator == value) {
+                    Some((name, _)) => name.clone(),
+                    None => value.to_string(),
+                }
+            }
+            _ => ty.format_scalar(bytes),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn print(&self) {
         for file in &self.files {
@@ -138,6 +540,7 @@ impl DwarfData {
                         var.name, var.entity_type.name, var.location, var.line_number
                     );
                 }
+                print_scopes(&func.scopes, 2);
             }
 
             println!("Line numbers:");
@@ -148,32 +551,169 @@ impl DwarfData {
     }
 }
 
+fn print_scopes(scopes: &[Scope], indent: usize) {
+    let pad = "  ".repeat(indent);
+    for scope in scopes {
+        let ranges: Vec<String> = scope
+            .ranges
+            .iter()
+            .map(|(start, end)| format!("{:#x}-{:#x}", start, end))
+            .collect();
+        match &scope.inlined_function {
+            Some(name) => println!("{}* Inlined {} (at {})", pad, name, ranges.join(", ")),
+            None => println!("{}* Block (at {})", pad, ranges.join(", ")),
+        }
+        for var in &scope.variables {
+            println!(
+                "{}  * Variable: {} ({}, located at {}, declared at line {})",
+                pad, var.name, var.entity_type.name, var.location, var.line_number
+            );
+        }
+        print_scopes(&scope.scopes, indent + 1);
+    }
+}
+
+/// Offset of a type's DIE in .debug_info, which identifies it across compilation units.
+pub type TypeId = usize;
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
+    /// The type as C spells it, e.g. "struct node *"
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+    /// Where the type is defined in the DWARF, or None for types the debugger makes up
+    pub id: Option<TypeId>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum TypeKind {
+    /// int, char, double and the like
+    #[default]
+    Base,
+    /// Points at the given type, or at void
+    Pointer(Option<TypeId>),
+    /// The count is None for arrays of unknown length, like `extern int table[];`
+    Array { element: TypeId, count: Option<usize> },
+    Struct(Vec<Member>),
+    Union(Vec<Member>),
+    /// Names and values of the enumerators
+    Enum(Vec<(String, i64)>),
+    Typedef(Option<TypeId>),
+    /// const, volatile or restrict
+    Qualified(&'static str, Option<TypeId>),
+    /// A function returning the given type, or void. Only reachable through function pointers.
+    Function(Option<TypeId>),
+}
+
+/// A field of a struct or union. Anonymous struct and union fields have an empty name.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub type_id: TypeId,
+    /// Offset from the start of the enclosing struct, in bytes
+    pub offset: usize,
 }
 
 impl Type {
     pub fn new(name: String, size: usize) -> Self {
         Type {
-            name: name,
-            size: size,
+            name,
+            size,
+            kind: TypeKind::Base,
+            id: None,
+        }
+    }
+
+    /// Formats the raw (little-endian) bytes of a scalar, based on the base type name and size.
+    fn format_scalar(&self, bytes: &[u8]) -> String {
+        if bytes.len() > size_of::<u64>() || bytes.len() != self.size {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:#04x}", b)).collect();
+            return format!("{{{}}}", hex.join(", "));
         }
+        let raw = raw_value(bytes);
+
+        match (self.name.as_str(), bytes.len()) {
+            ("float", 4) => return format_float(f32::from_bits(raw as u32)),
+            ("double", 8) => return format_float(f64::from_bits(raw)),
+            ("_Bool", _) => return (raw != 0).to_string(),
+            _ => {}
+        }
+
+        let value = if self.name.contains("unsigned") || bytes.is_empty() {
+            raw.to_string()
+        } else {
+            sign_extend(raw, bytes.len()).to_string()
+        };
+        if self.is_char() {
+            format!("{} {}", value, quote_char(bytes[0], '\''))
+        } else {
+            value
+        }
+    }
+
+    /// Whether this is a character type, whose arrays and pointers are printed as strings.
+    pub fn is_char(&self) -> bool {
+        matches!(self.kind, TypeKind::Base) && self.size == 1 && self.name.contains("char")
+    }
+}
+
+/// Formats a floating-point value, switching to exponent notation for very small and very large
+/// magnitudes, where the plain notation would be a long run of zeros.
+fn format_float<F: Into<f64> + Copy + fmt::Display + fmt::LowerExp>(value: F) -> String {
+    let magnitude = value.into().abs();
+    if magnitude != 0.0 && magnitude.is_finite() && !(1e-4..1e16).contains(&magnitude) {
+        format!("{:e}", value)
+    } else {
+        value.to_string()
     }
 }
 
+/// Zero-extends up to 8 little-endian bytes to a u64.
+fn raw_value(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+/// Sign-extends a `size`-byte integer to 64 bits.
+fn sign_extend(raw: u64, size: usize) -> i64 {
+    let shift = 64 - 8 * size;
+    ((raw << shift) as i64) >> shift
+}
+
 #[derive(Clone)]
 pub enum Location {
     Address(usize),
     FramePointerOffset(isize),
+    /// Any other DWARF expression, evaluated in the variable's frame when it is read
+    Expression(Vec<u8>, gimli::Encoding),
+    /// Location list: (start, end, location) for each range of pcs the variable is available in
+    List(Vec<(usize, usize, Location)>),
+    /// Value the compiler folded into a constant (DW_AT_const_value)
+    Constant(Vec<u8>),
+    /// The variable exists in the source but not in the compiled code
+    OptimizedOut,
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::Expression(bytes, _) => write!(f, "Expression({:02x?})", bytes),
+            Location::List(entries) => {
+                write!(f, "List(")?;
+                for (i, (start, end, location)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "[{:#x}, {:#x}): {}", start, end, location)?;
+                }
+                write!(f, ")")
+            }
+            Location::Constant(bytes) => write!(f, "Constant({:02x?})", bytes),
+            Location::OptimizedOut => write!(f, "OptimizedOut"),
         }
     }
 }
@@ -199,12 +739,42 @@ pub struct Function {
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
+    /// DW_AT_frame_base, which DW_OP_fbreg locations of the function's variables are relative to
+    pub frame_base: Option<Location>,
+    /// Parameters and the variables declared in the outermost block of the function
+    pub variables: Vec<Variable>,
+    /// Nested blocks and inlined calls, with the variables declared in them
+    pub scopes: Vec<Scope>,
+}
+
+/// A lexical block or the body of an inlined function call within a function.
+#[derive(Debug, Default, Clone)]
+pub struct Scope {
+    /// Name of the inlined function, or None for a lexical block
+    pub inlined_function: Option<String>,
+    /// Ranges of addresses [start, end) of the scope's code
+    pub ranges: Vec<(usize, usize)>,
     pub variables: Vec<Variable>,
+    pub scopes: Vec<Scope>,
+}
+
+impl Scope {
+    pub fn contains(&self, addr: usize) -> bool {
+        self.ranges.iter().any(|(start, end)| *start <= addr && addr < *end)
+    }
+}
+
+/// One function in the chain of inlined calls executing at an address
+pub struct InlineFrame {
+    pub function: Option<String>,
+    pub line: Option<Line>,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct File {
     pub name: String,
+    /// Absolute path of the source file
+    pub path: String,
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
     pub lines: Vec<Line>,
@@ -0,0 +1,66 @@
+//! The errors deet runs into. Commands report them and return to the prompt.
+
+use crate::dwarf_data;
+use rustyline::error::ReadlineError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum DebuggerError {
+    /// The target's debugging symbols could not be read
+    Dwarf(dwarf_data::Error),
+    /// The terminal could not be set up or read from
+    Readline(ReadlineError),
+    /// The target program could not be started
+    Spawn(std::io::Error),
+    /// A ptrace or waitpid call failed, usually because the process is gone
+    Ptrace(nix::Error),
+    /// waitpid reported a status deet has no use for
+    UnexpectedStatus(String),
+    /// The command needs a process, but there is none
+    NoProcess,
+    /// A register name that isn't one of `REGISTER_NAMES`
+    UnknownRegister(String),
+    /// The frame can't be unwound, because there is no call frame information for it
+    NoUnwindInfo,
+    /// A source file, line or function that can't be found, with the reason
+    Location(String),
+}
+
+impl fmt::Display for DebuggerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DebuggerError::Dwarf(dwarf_data::Error::ErrorOpeningFile) => write!(f, "could not open file"),
+            DebuggerError::Dwarf(dwarf_data::Error::DwarfFormatError(err)) => {
+                write!(f, "could not read debugging symbols: {:?}", err)
+            }
+            DebuggerError::Readline(err) => write!(f, "terminal error: {}", err),
+            DebuggerError::Spawn(err) => write!(f, "could not start the program: {}", err),
+            DebuggerError::Ptrace(err) => write!(f, "{}", err),
+            DebuggerError::UnexpectedStatus(status) => {
+                write!(f, "waitpid returned unexpected status: {}", status)
+            }
+            DebuggerError::NoProcess => write!(f, "The program is not being run."),
+            DebuggerError::UnknownRegister(name) => write!(f, "Invalid register `{}'", name),
+            DebuggerError::NoUnwindInfo => write!(f, "No unwind information for the frame."),
+            DebuggerError::Location(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl From<dwarf_data::Error> for DebuggerError {
+    fn from(err: dwarf_data::Error) -> Self {
+        DebuggerError::Dwarf(err)
+    }
+}
+
+impl From<ReadlineError> for DebuggerError {
+    fn from(err: ReadlineError) -> Self {
+        DebuggerError::Readline(err)
+    }
+}
+
+impl From<nix::Error> for DebuggerError {
+    fn from(err: nix::Error) -> Self {
+        DebuggerError::Ptrace(err)
+    }
+}
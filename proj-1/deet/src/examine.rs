@@ -0,0 +1,232 @@
+//! Formatting for the `x/NFU` memory examination command.
+
+/// Most units, strings or instructions a single `x` command shows
+pub const MAX_COUNT: usize = 100_000;
+
+/// Parsed `/NFU` suffix of an `x` command: how many units to show, in which format, and how big
+/// each unit is.
+#[derive(Clone, Copy)]
+pub struct ExamineFormat {
+    pub count: usize,
+    /// One of x (hex), d (signed decimal), u (unsigned decimal), c (char), s (string),
+    /// a (address) or i (instruction)
+    pub format: char,
+    /// Unit size in bytes
+    pub unit: usize,
+}
+
+impl Default for ExamineFormat {
+    fn default() -> Self {
+        ExamineFormat {
+            count: 1,
+            format: 'x',
+            unit: 4,
+        }
+    }
+}
+
+impl ExamineFormat {
+    /// Parses an `NFU` spec such as "4xg". Parts that are left out are taken from `last`, except
+    /// the count, which defaults to 1.
+    pub fn parse(spec: &str, last: ExamineFormat) -> Result<ExamineFormat, String> {
+        let digits = spec.chars().take_while(|c| c.is_ascii_digit()).count();
+        let count = match &spec[..digits] {
+            "" => 1,
+            count => count.parse().map_err(|_| format!("Invalid count \"{}\"", count))?,
+        };
+        if count > MAX_COUNT {
+            return Err(format!("Count {} is too large; the limit is {}.", count, MAX_COUNT));
+        }
+        let mut format = None;
+        let mut unit = None;
+        for letter in spec[digits..].chars() {
+            match letter {
+                'b' => unit = Some(1),
+                'h' => unit = Some(2),
+                'w' => unit = Some(4),
+                'g' => unit = Some(8),
+                'x' | 'd' | 'u' | 'c' | 's' | 'a' | 'i' => format = Some(letter),
+                _ => return Err(format!("Invalid format letter '{}'", letter)),
+            }
+        }
+        let format = format.unwrap_or(last.format);
+        // Like gdb, chars and addresses have a natural size unless one is given explicitly
+        let unit = match (format, unit) {
+            (_, Some(unit)) => unit,
+            ('c', None) => 1,
+            ('a', None) => 8,
+            (_, None) if matches!(last.format, 'x' | 'd' | 'u') => last.unit,
+            (_, None) => 4,
+        };
+        Ok(ExamineFormat {
+            count,
+            format,
+            unit,
+        })
+    }
+
+    /// Number of units printed on each line, as gdb does.
+    pub fn units_per_line(&self) -> usize {
+        match (self.format, self.unit) {
+            ('c', _) | (_, 1) | (_, 2) => 8,
+            (_, 4) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Formats one unit of memory. `bytes` is `self.unit` bytes long and little-endian. Addresses
+    /// are formatted by the caller, which can label them with symbols.
+    pub fn format_unit(&self, bytes: &[u8]) -> String {
+        let unsigned = unit_value(bytes);
+        let shift = 64 - 8 * bytes.len() as u32;
+        let signed = ((unsigned << shift) as i64) >> shift;
+        match self.format {
+            'd' => signed.to_string(),
+            'u' => unsigned.to_string(),
+            'c' => format!("{} {}", signed, quote_char(bytes[0], '\'')),
+            _ => format!("{:#0width$x}", unsigned, width = 2 + 2 * bytes.len()),
+        }
+    }
+}
+
+/// Reads a little-endian unsigned value of up to 8 bytes.
+pub fn unit_value(bytes: &[u8]) -> u64 {
+    let mut raw = [0u8; 8];
+    raw[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(raw)
+}
+
+/// Reads a NUL-terminated string of at most `max_len` characters, using `read(addr, len)` to read
+/// memory. Returns the string without its NUL, and whether the NUL was found. Fails only if
+/// nothing at all could be read.
+pub fn read_c_string<E>(
+    addr: usize,
+    max_len: usize,
+    read: impl Fn(usize, usize) -> Result<Vec<u8>, E>,
+) -> Result<(Vec<u8>, bool), E> {
+    let word_size = std::mem::size_of::<usize>();
+    let mut string = Vec::new();
+    let mut terminated = false;
+    while !terminated && string.len() < max_len {
+        // Read up to the next word boundary, so that a read never crosses into a page past the
+        // end of the string
+        let curr_addr = addr + string.len();
+        let chunk = match read(curr_addr, word_size - curr_addr % word_size) {
+            Ok(chunk) => chunk,
+            Err(err) if string.is_empty() => return Err(err),
+            Err(_) => break,
+        };
+        match chunk.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                string.extend_from_slice(&chunk[..end]);
+                terminated = true;
+            }
+            None => string.extend_from_slice(&chunk),
+        }
+    }
+    if string.len() > max_len {
+        string.truncate(max_len);
+        terminated = false;
+    }
+    Ok((string, terminated))
+}
+
+/// Quotes a byte as a C character, escaping it if it isn't printable.
+pub fn quote_char(byte: u8, quote: char) -> String {
+    format!("{}{}{}", quote, escape_byte(byte, quote), quote)
+}
+
+/// Quotes a C string, escaping the characters that aren't printable.
+pub fn quote_string(bytes: &[u8]) -> String {
+    let escaped: String = bytes.iter().map(|byte| escape_byte(*byte, '"')).collect();
+    format!("\"{}\"", escaped)
+}
+
+fn escape_byte(byte: u8, quote: char) -> String {
+    match byte {
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'\r' => "\\r".to_string(),
+        b'\\' => "\\\\".to_string(),
+        0 => "\\000".to_string(),
+        _ if byte as char == quote => format!("\\{}", quote),
+        _ if byte.is_ascii_graphic() || byte == b' ' => (byte as char).to_string(),
+        _ => format!("\\{:03o}", byte),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(spec: &str) -> Result<ExamineFormat, String> {
+        ExamineFormat::parse(spec, ExamineFormat::default())
+    }
+
+    #[test]
+    fn test_parse_full_spec() {
+        let format = parse("4xg").unwrap();
+        assert_eq!((format.count, format.format, format.unit), (4, 'x', 8));
+        let format = parse("16db").unwrap();
+        assert_eq!((format.count, format.format, format.unit), (16, 'd', 1));
+    }
+
+    #[test]
+    fn test_parse_letters_in_any_order() {
+        let format = parse("2hu").unwrap();
+        assert_eq!((format.count, format.format, format.unit), (2, 'u', 2));
+    }
+
+    #[test]
+    fn test_parse_empty_spec() {
+        let format = parse("").unwrap();
+        assert_eq!((format.count, format.format, format.unit), (1, 'x', 4));
+    }
+
+    #[test]
+    fn test_parse_defaults_from_last() {
+        let last = parse("8dg").unwrap();
+        // The count always defaults to 1, but the format and size carry over
+        let format = ExamineFormat::parse("", last).unwrap();
+        assert_eq!((format.count, format.format, format.unit), (1, 'd', 8));
+        let format = ExamineFormat::parse("3", last).unwrap();
+        assert_eq!((format.count, format.format, format.unit), (3, 'd', 8));
+        let format = ExamineFormat::parse("b", last).unwrap();
+        assert_eq!((format.count, format.format, format.unit), (1, 'd', 1));
+    }
+
+    #[test]
+    fn test_parse_natural_sizes() {
+        let last = parse("xg").unwrap();
+        assert_eq!(ExamineFormat::parse("c", last).unwrap().unit, 1);
+        assert_eq!(ExamineFormat::parse("a", last).unwrap().unit, 8);
+        assert_eq!(ExamineFormat::parse("ch", last).unwrap().unit, 2);
+        // Sizes of other formats don't carry over to strings and instructions
+        assert_eq!(ExamineFormat::parse("s", parse("c").unwrap()).unwrap().unit, 4);
+    }
+
+    #[test]
+    fn test_parse_invalid_letter() {
+        assert!(parse("4q").is_err());
+        assert!(parse("x4").is_err());
+    }
+
+    #[test]
+    fn test_parse_count_limits() {
+        assert_eq!(parse(&format!("{}x", MAX_COUNT)).unwrap().count, MAX_COUNT);
+        assert!(parse(&format!("{}x", MAX_COUNT + 1)).is_err());
+        assert!(parse("1000000000xg").is_err());
+        // Too large to fit in a usize at all
+        assert!(parse("99999999999999999999999x").is_err());
+    }
+
+    #[test]
+    fn test_format_unit() {
+        let hex = parse("xb").unwrap();
+        assert_eq!(hex.format_unit(&[0xff]), "0xff");
+        let signed = parse("dh").unwrap();
+        assert_eq!(signed.format_unit(&[0xfe, 0xff]), "-2");
+        let unsigned = parse("uh").unwrap();
+        assert_eq!(unsigned.format_unit(&[0xfe, 0xff]), "65534");
+    }
+}
@@ -0,0 +1,1219 @@
+//! A small C expression evaluator over the inferior's memory, used by `print`, `display`, `set
+//! var`, `x` and breakpoint conditions. It supports arithmetic, comparisons, `&`, `*`, `.`, `->`,
+//! array indexing and casts to types the program's debugging information knows about.
+
+use crate::dwarf_data::{DwarfData, Type, TypeKind};
+use crate::examine::unit_value;
+use crate::inferior::{register_field, Inferior};
+use crate::location::{self, Place};
+use crate::unwind::Frame;
+use std::fmt;
+use std::mem::size_of;
+
+/// Operators, longest first so that "<=" isn't taken for "<"
+const PUNCTUATION: [&str; 27] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&",
+    "|", "^", "!", "~", "(", ")", "[", "]", ".", ",",
+];
+
+/// Binary operators and their precedence; higher binds tighter
+const BINARY_OPERATORS: [(&str, u8); 18] = [
+    ("||", 1),
+    ("&&", 2),
+    ("|", 3),
+    ("^", 4),
+    ("&", 5),
+    ("==", 6),
+    ("!=", 6),
+    ("<", 7),
+    ("<=", 7),
+    (">", 7),
+    (">=", 7),
+    ("<<", 8),
+    (">>", 8),
+    ("+", 9),
+    ("-", 9),
+    ("*", 10),
+    ("/", 10),
+    ("%", 10),
+];
+
+/// Words that can make up the name of a base type, like "unsigned long int"
+const BASE_TYPE_WORDS: [&str; 11] = [
+    "signed", "unsigned", "short", "long", "int", "char", "float", "double", "_Bool", "const",
+    "volatile",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Value, and the type its size and suffix give it
+    Integer(u64, IntType),
+    Float(f64),
+    Char(u8),
+    Identifier(String),
+    /// `$rip` and the like, without the `$`
+    Register(String),
+    Punct(&'static str),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Integer(u64, IntType),
+    Float(f64),
+    Char(u8),
+    Identifier(String),
+    Register(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Member(Box<Expr>, String),
+    Arrow(Box<Expr>, String),
+    Cast(Type, Box<Expr>),
+}
+
+/// A parsed expression, which can be evaluated again each time the inferior stops.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    text: String,
+    expr: Expr,
+}
+
+/// The result of evaluating an expression.
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub ty: Type,
+    /// Raw little-endian bytes of the value, `ty.size` of them
+    pub bytes: Vec<u8>,
+    /// Where the value is stored in the inferior, if it is stored anywhere
+    pub addr: Option<usize>,
+    /// The compiler got rid of the value, so `bytes` are meaningless
+    pub optimized_out: bool,
+}
+
+/// What an expression is evaluated against.
+pub struct Context<'a> {
+    pub debug_data: &'a DwarfData,
+    /// None before the program runs, when only the addresses of globals are known
+    pub inferior: Option<&'a Inferior>,
+    /// Frame whose locals names refer to
+    pub frame: Option<&'a Frame>,
+}
+
+/// Integer types after C's usual arithmetic conversions
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IntType {
+    size: usize,
+    unsigned: bool,
+}
+
+/// A value as an operand of arithmetic
+enum Scalar {
+    Int(i128, IntType),
+    Float(f64),
+    /// Address, size of the pointed-to type and the type of the pointer
+    Pointer(u64, usize, Type),
+}
+
+impl Expression {
+    pub fn parse(text: &str, debug_data: &DwarfData) -> Result<Expression, String> {
+        let tokens = tokenize(text)?;
+        if tokens.is_empty() {
+            return Err("Missing expression".to_string());
+        }
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            debug_data,
+        };
+        let expr = parser.parse_binary(1)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("A syntax error in expression, near `{}'.", describe_token(token)));
+        }
+        Ok(Expression {
+            text: text.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn evaluate(&self, ctx: &Context) -> Result<Value, String> {
+        ctx.eval(&self.expr)
+    }
+
+    /// Evaluates an expression that must refer to memory, like `p->next` or `arr[2]`, and
+    /// returns its address and type without reading it.
+    pub fn evaluate_lvalue(&self, ctx: &Context) -> Result<(usize, Type), String> {
+        match ctx.eval_lvalue(&self.expr)? {
+            Some(lvalue) => Ok(lvalue),
+            None => {
+                // Evaluate it anyway, since an unknown name is a more useful error to report
+                ctx.eval(&self.expr)?;
+                Err("Attempt to take address of value not located in memory.".to_string())
+            }
+        }
+    }
+
+    /// Whether the expression is true in the sense of C: a nonzero scalar.
+    pub fn is_true(&self, ctx: &Context) -> Result<bool, String> {
+        ctx.is_nonzero(&self.evaluate(ctx)?)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) {
+            let start = i;
+            // In hex literals, e is a digit rather than the start of an exponent, so 0x1e+5 is a
+            // sum
+            let hex = c == '0' && matches!(chars.get(i + 1), Some('x' | 'X'));
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric()
+                    || chars[i] == '.'
+                    || (!hex
+                        && matches!(chars[i], '+' | '-')
+                        && matches!(chars[i - 1], 'e' | 'E')))
+            {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(parse_number(&number)?);
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(match word.strip_prefix('$') {
+                Some(register) => Token::Register(register.to_string()),
+                None => Token::Identifier(word),
+            });
+        } else if c == '\'' {
+            let (byte, len) = match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some('\\'), Some(escaped)) => {
+                    let byte = match escaped {
+                        'n' => b'\n',
+                        't' => b'\t',
+                        'r' => b'\r',
+                        '0' => 0,
+                        '\\' | '\'' | '"' => *escaped as u8,
+                        _ => return Err(format!("Unsupported escape sequence \\{}", escaped)),
+                    };
+                    (byte, 4)
+                }
+                (Some(c), _) if c.is_ascii() => (*c as u8, 3),
+                _ => return Err("Unmatched single quote.".to_string()),
+            };
+            if chars.get(i + len - 1) != Some(&'\'') {
+                return Err("Unmatched single quote.".to_string());
+            }
+            tokens.push(Token::Char(byte));
+            i += len;
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            let punct = PUNCTUATION
+                .iter()
+                .find(|punct| rest.starts_with(*punct))
+                .ok_or_else(|| format!("Invalid character '{}' in expression.", c))?;
+            tokens.push(Token::Punct(punct));
+            i += punct.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses an integer or floating-point literal, with C's `u`, `l` and `f` suffixes.
+fn parse_number(text: &str) -> Result<Token, String> {
+    let invalid = || format!("Invalid number \"{}\".", text);
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        let digits = hex.trim_end_matches(['u', 'l']);
+        let value = u64::from_str_radix(digits, 16).map_err(|_| invalid())?;
+        return Ok(Token::Integer(value, literal_type(value, &hex[digits.len()..])));
+    }
+    if lower.contains(['.', 'e']) {
+        let digits = lower.strip_suffix('f').unwrap_or(&lower);
+        return digits.parse().map(Token::Float).map_err(|_| invalid());
+    }
+    let digits = lower.trim_end_matches(['u', 'l']);
+    let value = if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    }
+    .map_err(|_| invalid())?;
+    Ok(Token::Integer(value, literal_type(value, &lower[digits.len()..])))
+}
+
+/// The type of an integer literal: the first of int, unsigned int, long and unsigned long that
+/// can hold the value and agrees with the suffix.
+fn literal_type(value: u64, suffix: &str) -> IntType {
+    let unsigned = suffix.contains('u');
+    let long = suffix.contains('l');
+    let fits_int = value <= i32::MAX as u64 && !unsigned;
+    let fits_unsigned_int = value <= u32::MAX as u64 && unsigned;
+    IntType {
+        size: if (fits_int || fits_unsigned_int) && !long { 4 } else { 8 },
+        unsigned: unsigned || value > i64::MAX as u64,
+    }
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Integer(value, _) => value.to_string(),
+        Token::Float(value) => value.to_string(),
+        Token::Char(byte) => format!("'{}'", *byte as char),
+        Token::Identifier(name) => name.clone(),
+        Token::Register(name) => format!("${}", name),
+        Token::Punct(punct) => punct.to_string(),
+    }
+}
+
+/// Recursive descent parser. Types are looked up while parsing, since C can't tell a cast from
+/// a parenthesized expression without knowing which names are types.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    debug_data: &'a DwarfData,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_punct(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Punct(punct)) => Some(punct),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.peek_punct() == Some(punct) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("Expected `{}' in expression.", punct))
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.parse_unary()?;
+        while let Some((op, precedence)) = self.peek_punct().and_then(|punct| {
+            BINARY_OPERATORS
+                .iter()
+                .find(|(op, precedence)| *op == punct && *precedence >= min_precedence)
+        }) {
+            self.pos += 1;
+            let rhs = self.parse_binary(precedence + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek_punct() {
+            Some(op @ ("-" | "+" | "!" | "~" | "*" | "&")) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            Some("(") => match self.parse_cast_type()? {
+                Some(ty) => Ok(Expr::Cast(ty, Box::new(self.parse_unary()?))),
+                None => self.parse_postfix(),
+            },
+            _ => self.parse_postfix(),
+        }
+    }
+
+    /// Parses `(TYPE)` if the parentheses hold a type name, and otherwise leaves them alone.
+    fn parse_cast_type(&mut self) -> Result<Option<Type>, String> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut words = Vec::new();
+        while let Some(Token::Identifier(word)) = self.peek() {
+            words.push(word.clone());
+            self.pos += 1;
+        }
+        let mut pointers = 0;
+        while self.peek_punct() == Some("*") {
+            pointers += 1;
+            self.pos += 1;
+        }
+        let ty = match self.peek_punct() {
+            Some(")") if !words.is_empty() => self.lookup_type(&words, pointers)?,
+            _ => None,
+        };
+        match ty {
+            Some(ty) => {
+                self.pos += 1;
+                Ok(Some(ty))
+            }
+            None => {
+                self.pos = start;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Looks up a type named by `words`, like ["struct", "node"] or ["unsigned", "long"],
+    /// followed by `pointers` stars. Returns None if the words don't name a type.
+    fn lookup_type(&self, words: &[String], pointers: usize) -> Result<Option<Type>, String> {
+        let words: Vec<&str> = words
+            .iter()
+            .map(|word| word.as_str())
+            .filter(|word| !matches!(*word, "const" | "volatile"))
+            .collect();
+        let mut ty = match words.as_slice() {
+            [] => return Ok(None),
+            ["void"] => Type {
+                name: "void".to_string(),
+                ..Default::default()
+            },
+            [tag @ ("struct" | "union" | "enum"), name] => {
+                let name = format!("{} {}", tag, name);
+                match self.debug_data.find_type(&name) {
+                    Some(ty) => ty.clone(),
+                    None => return Err(format!("No {}.", name)),
+                }
+            }
+            _ if words.iter().all(|word| BASE_TYPE_WORDS.contains(word)) => {
+                let (name, size) = base_type_name(&words)
+                    .ok_or_else(|| format!("Invalid type \"{}\".", words.join(" ")))?;
+                named_type(self.debug_data, name, size)
+            }
+            [name] => match self.debug_data.find_type(name) {
+                Some(ty) if matches!(ty.kind, TypeKind::Typedef(_) | TypeKind::Base) => ty.clone(),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        for _ in 0..pointers {
+            ty = if ty.name == "void" {
+                Type {
+                    name: "void *".to_string(),
+                    size: size_of::<usize>(),
+                    kind: TypeKind::Pointer(None),
+                    id: None,
+                }
+            } else {
+                self.debug_data.pointer_to(&ty)
+            };
+        }
+        if ty.name == "void" {
+            return Err("Invalid cast to void.".to_string());
+        }
+        Ok(Some(ty))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek_punct() {
+                Some("[") => {
+                    self.pos += 1;
+                    let index = self.parse_binary(1)?;
+                    self.expect("]")?;
+                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                }
+                Some(op @ ("." | "->")) => {
+                    self.pos += 1;
+                    let field = match self.peek() {
+                        Some(Token::Identifier(field)) => field.clone(),
+                        _ => return Err(format!("Expected a field name after `{}'.", op)),
+                    };
+                    self.pos += 1;
+                    expr = if op == "." {
+                        Expr::Member(Box::new(expr), field)
+                    } else {
+                        Expr::Arrow(Box::new(expr), field)
+                    };
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| "Unexpected end of expression.".to_string())?;
+        self.pos += 1;
+        match token {
+            Token::Integer(value, ty) => Ok(Expr::Integer(value, ty)),
+            Token::Float(value) => Ok(Expr::Float(value)),
+            Token::Char(byte) => Ok(Expr::Char(byte)),
+            Token::Identifier(name) => Ok(Expr::Identifier(name)),
+            Token::Register(name) => Ok(Expr::Register(name)),
+            Token::Punct("(") => {
+                let expr = self.parse_binary(1)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            token => Err(format!("A syntax error in expression, near `{}'.", describe_token(&token))),
+        }
+    }
+}
+
+/// Spells a base type the way gcc names it in DWARF, e.g. "long unsigned int" for "unsigned
+/// long". Returns the name and size.
+fn base_type_name(words: &[&str]) -> Option<(&'static str, usize)> {
+    let count = |word: &str| words.iter().filter(|w| **w == word).count();
+    let unsigned = count("unsigned") > 0;
+    if unsigned && count("signed") > 0 {
+        return None;
+    }
+    let longs = count("long");
+    Some(if count("char") > 0 {
+        let name = match (unsigned, count("signed") > 0) {
+            (true, _) => "unsigned char",
+            (_, true) => "signed char",
+            _ => "char",
+        };
+        (name, 1)
+    } else if count("_Bool") > 0 {
+        ("_Bool", 1)
+    } else if count("float") > 0 {
+        ("float", 4)
+    } else if count("double") > 0 {
+        if longs > 0 {
+            ("long double", 16)
+        } else {
+            ("double", 8)
+        }
+    } else if count("short") > 0 {
+        (if unsigned { "short unsigned int" } else { "short int" }, 2)
+    } else if longs >= 2 {
+        (if unsigned { "long long unsigned int" } else { "long long int" }, 8)
+    } else if longs == 1 {
+        (if unsigned { "long unsigned int" } else { "long int" }, 8)
+    } else {
+        (if unsigned { "unsigned int" } else { "int" }, 4)
+    })
+}
+
+/// The program's own base type with this name, or a stand-in if it doesn't use the type.
+fn named_type(debug_data: &DwarfData, name: &str, size: usize) -> Type {
+    match debug_data.find_type(name) {
+        Some(ty) => ty.clone(),
+        None => Type::new(name.to_string(), size),
+    }
+}
+
+fn int_type_name(ty: IntType) -> &'static str {
+    match (ty.size, ty.unsigned) {
+        (8, true) => "long unsigned int",
+        (8, false) => "long int",
+        (_, true) => "unsigned int",
+        (_, false) => "int",
+    }
+}
+
+/// Wraps an integer around to the range of `ty`.
+fn truncate(value: i128, ty: IntType) -> i128 {
+    let bits = 8 * ty.size as u32;
+    let raw = value & ((1i128 << bits) - 1);
+    if !ty.unsigned && raw >> (bits - 1) != 0 {
+        raw - (1i128 << bits)
+    } else {
+        raw
+    }
+}
+
+/// C's integer promotion: anything smaller than int becomes int.
+fn promote(ty: IntType) -> IntType {
+    if ty.size < 4 {
+        IntType {
+            size: 4,
+            unsigned: false,
+        }
+    } else {
+        ty
+    }
+}
+
+/// C's usual arithmetic conversions, for integer operands.
+fn common_type(lhs: IntType, rhs: IntType) -> IntType {
+    let (lhs, rhs) = (promote(lhs), promote(rhs));
+    let size = lhs.size.max(rhs.size);
+    IntType {
+        size,
+        unsigned: (lhs.unsigned && lhs.size == size) || (rhs.unsigned && rhs.size == size),
+    }
+}
+
+impl Context<'_> {
+    fn eval(&self, expr: &Expr) -> Result<Value, String> {
+        // Variables optimized into registers or away have a value but no address
+        if let Expr::Identifier(name) = expr {
+            return self.eval_identifier(name);
+        }
+        if let Some((addr, ty)) = self.eval_lvalue(expr)? {
+            return self.load(addr, ty);
+        }
+        match expr {
+            Expr::Integer(value, ty) => Ok(self.int_value(*value as i128, *ty)),
+            Expr::Float(value) => Ok(self.float_value(*value)),
+            Expr::Char(byte) => Ok(Value {
+                ty: named_type(self.debug_data, "char", 1),
+                bytes: vec![*byte],
+                addr: None,
+                optimized_out: false,
+            }),
+            Expr::Register(name) => {
+                let inferior = self.inferior.ok_or("The program has no registers now.")?;
+                let mut regs = inferior.get_registers().map_err(|err| err.to_string())?;
+                let value = *register_field(&mut regs, name)
+                    .ok_or_else(|| format!("Invalid register \"${}\"", name))?;
+                Ok(self.int_value(value as i64 as i128, IntType { size: 8, unsigned: false }))
+            }
+            Expr::Unary("&", operand) => {
+                let (addr, ty) = self
+                    .eval_lvalue(operand)?
+                    .ok_or("Attempt to take address of value not located in memory.")?;
+                Ok(Value {
+                    ty: self.debug_data.pointer_to(&ty),
+                    bytes: (addr as u64).to_le_bytes().to_vec(),
+                    addr: None,
+                    optimized_out: false,
+                })
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                self.unary(op, &value)
+            }
+            Expr::Binary(op @ ("&&" | "||"), lhs, rhs) => {
+                // Like in C, the right operand is only evaluated if it matters
+                let lhs = self.is_nonzero(&self.eval(lhs)?)?;
+                let result = match *op {
+                    "&&" => lhs && self.is_nonzero(&self.eval(rhs)?)?,
+                    _ => lhs || self.is_nonzero(&self.eval(rhs)?)?,
+                };
+                Ok(self.bool_value(result))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                self.binary(op, &lhs, &rhs)
+            }
+            Expr::Cast(ty, operand) => {
+                let value = self.eval(operand)?;
+                Ok(Value {
+                    bytes: self.convert(&value, ty)?,
+                    ty: ty.clone(),
+                    addr: None,
+                    optimized_out: false,
+                })
+            }
+            Expr::Member(base, field) => {
+                // A structure that isn't in memory, such as one kept in registers
+                let value = self.eval(base)?;
+                let (offset, ty) = self.member(0, &value.ty, field)?;
+                let bytes = match value.bytes.get(offset..offset + ty.size) {
+                    Some(bytes) if !value.optimized_out => bytes.to_vec(),
+                    _ => vec![0; ty.size],
+                };
+                Ok(Value {
+                    ty,
+                    bytes,
+                    addr: None,
+                    optimized_out: value.optimized_out,
+                })
+            }
+            Expr::Identifier(_) | Expr::Index(..) | Expr::Arrow(..) => unreachable!("handled above"),
+        }
+    }
+
+    /// Evaluates expressions that designate memory to the address and type of that memory,
+    /// without reading it. Returns None for other expressions.
+    fn eval_lvalue(&self, expr: &Expr) -> Result<Option<(usize, Type)>, String> {
+        match expr {
+            Expr::Identifier(name) => match self.variable(name)? {
+                Some((Place::Memory(addr), ty)) => Ok(Some((addr, ty))),
+                _ => Ok(None),
+            },
+            Expr::Unary("*", operand) => self.pointee(operand).map(Some),
+            Expr::Index(base, index) => {
+                let (addr, element) = self.pointee(base)?;
+                let index = match self.scalar(&self.eval(index)?)? {
+                    Scalar::Int(index, _) => index,
+                    _ => return Err("Array index must be an integer.".to_string()),
+                };
+                let addr = (addr as i128 + index * element.size as i128) as usize;
+                Ok(Some((addr, element)))
+            }
+            Expr::Arrow(base, field) => {
+                let (addr, ty) = self.pointee(base)?;
+                self.member(addr, &ty, field).map(Some)
+            }
+            Expr::Member(base, field) => match self.eval_lvalue(base)? {
+                Some((addr, ty)) => self.member(addr, &ty, field).map(Some),
+                None => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Finds what a pointer expression points to, or the first element of an array.
+    fn pointee(&self, expr: &Expr) -> Result<(usize, Type), String> {
+        let value = match self.eval_lvalue(expr)? {
+            Some((addr, ty)) => {
+                // Arrays decay to a pointer to their first element, without reading them
+                if let TypeKind::Array { element, .. } = self.debug_data.resolve_type(&ty).kind {
+                    return Ok((addr, self.get_type(element)?));
+                }
+                self.load(addr, ty)?
+            }
+            None => self.eval(expr)?,
+        };
+        match self.debug_data.resolve_type(&value.ty).kind {
+            TypeKind::Pointer(Some(target)) => {
+                Ok((unit_value(&value.bytes) as usize, self.get_type(target)?))
+            }
+            _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+        }
+    }
+
+    fn member(&self, addr: usize, ty: &Type, field: &str) -> Result<(usize, Type), String> {
+        let resolved = self.debug_data.resolve_type(ty);
+        if !matches!(resolved.kind, TypeKind::Struct(_) | TypeKind::Union(_)) {
+            return Err(match resolved.kind {
+                TypeKind::Pointer(_) => {
+                    format!("The value of type {} is a pointer; use -> to reach its fields.", ty.name)
+                }
+                _ => "Attempt to extract a component of a value that is not a structure.".to_string(),
+            });
+        }
+        let (offset, field_type) = self
+            .debug_data
+            .get_member(ty, field)
+            .ok_or_else(|| format!("There is no member named {}.", field))?;
+        Ok((addr + offset, field_type.clone()))
+    }
+
+    fn get_type(&self, id: usize) -> Result<Type, String> {
+        self.debug_data
+            .get_type(id)
+            .cloned()
+            .ok_or_else(|| "Attempt to use a type the debugging information doesn't describe.".to_string())
+    }
+
+    /// Looks up a variable, preferring locals of the frame to globals, and finds where its value
+    /// is in the frame.
+    fn variable(&self, name: &str) -> Result<Option<(Place, Type)>, String> {
+        let pc = self.frame.map_or(0, |frame| frame.lookup_addr());
+        let inline_depth = self.frame.map_or(0, |frame| frame.inline_depth);
+        let var = match self.debug_data.get_variable(pc, inline_depth, name) {
+            Some(var) => var,
+            None => return Ok(None),
+        };
+        let frame_base = self
+            .debug_data
+            .get_function_for_addr(pc)
+            .and_then(|func| func.frame_base.as_ref());
+        let place = location::locate(
+            &var.location,
+            var.entity_type.size,
+            frame_base,
+            self.frame,
+            self.inferior,
+        )?;
+        Ok(Some((place, var.entity_type.clone())))
+    }
+
+    fn eval_identifier(&self, name: &str) -> Result<Value, String> {
+        match self.variable(name)? {
+            Some((Place::Memory(addr), ty)) => self.load(addr, ty),
+            Some((Place::Value(bytes), ty)) => Ok(Value {
+                ty,
+                bytes,
+                addr: None,
+                optimized_out: false,
+            }),
+            Some((Place::OptimizedOut, ty)) => Ok(Value {
+                bytes: vec![0; ty.size],
+                ty,
+                addr: None,
+                optimized_out: true,
+            }),
+            None => self.eval_symbol(name),
+        }
+    }
+
+    /// Evaluates a name that isn't a variable: an enumerator, a function or true/false.
+    fn eval_symbol(&self, name: &str) -> Result<Value, String> {
+        if let Some((value, ty)) = self.debug_data.get_enumerator(name) {
+            return Ok(Value {
+                ty: ty.clone(),
+                bytes: value.to_le_bytes()[..ty.size.min(size_of::<i64>())].to_vec(),
+                addr: None,
+                optimized_out: false,
+            });
+        }
+        if let Ok(addr) = self.debug_data.get_addr_for_function(None, name) {
+            return Ok(Value {
+                ty: Type {
+                    name: "void (*)()".to_string(),
+                    size: size_of::<usize>(),
+                    kind: TypeKind::Pointer(None),
+                    id: None,
+                },
+                bytes: (addr as u64).to_le_bytes().to_vec(),
+                addr: None,
+                optimized_out: false,
+            });
+        }
+        match name {
+            "true" => Ok(self.bool_value(true)),
+            "false" => Ok(self.bool_value(false)),
+            _ => Err(format!("No symbol \"{}\" in current context.", name)),
+        }
+    }
+
+    fn load(&self, addr: usize, ty: Type) -> Result<Value, String> {
+        let inferior = self.inferior.ok_or("The program is not being run.")?;
+        let bytes = if ty.size == 0 {
+            Vec::new()
+        } else {
+            inferior
+                .read_bytes(addr, ty.size)
+                .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?
+        };
+        Ok(Value {
+            ty,
+            bytes,
+            addr: Some(addr),
+            optimized_out: false,
+        })
+    }
+
+    fn int_value(&self, value: i128, ty: IntType) -> Value {
+        Value {
+            ty: named_type(self.debug_data, int_type_name(ty), ty.size),
+            bytes: truncate(value, ty).to_le_bytes()[..ty.size].to_vec(),
+            addr: None,
+            optimized_out: false,
+        }
+    }
+
+    fn float_value(&self, value: f64) -> Value {
+        Value {
+            ty: named_type(self.debug_data, "double", size_of::<f64>()),
+            bytes: value.to_le_bytes().to_vec(),
+            addr: None,
+            optimized_out: false,
+        }
+    }
+
+    fn bool_value(&self, value: bool) -> Value {
+        self.int_value(value as i128, IntType { size: 4, unsigned: false })
+    }
+
+    fn is_nonzero(&self, value: &Value) -> Result<bool, String> {
+        Ok(match self.scalar(value)? {
+            Scalar::Int(value, _) => value != 0,
+            Scalar::Float(value) => value != 0.0,
+            Scalar::Pointer(addr, _, _) => addr != 0,
+        })
+    }
+
+    /// Interprets a value as an operand of arithmetic. Arrays decay to pointers.
+    fn scalar(&self, value: &Value) -> Result<Scalar, String> {
+        if value.optimized_out {
+            return Err("value has been optimized out".to_string());
+        }
+        let ty = self.debug_data.resolve_type(&value.ty);
+        let bytes = &value.bytes;
+        match &ty.kind {
+            TypeKind::Base if ty.name == "float" && bytes.len() == 4 => {
+                Ok(Scalar::Float(f32::from_bits(unit_value(bytes) as u32) as f64))
+            }
+            TypeKind::Base if ty.name == "double" && bytes.len() == 8 => {
+                Ok(Scalar::Float(f64::from_bits(unit_value(bytes))))
+            }
+            TypeKind::Base | TypeKind::Enum(_) if (1..=8).contains(&bytes.len()) => {
+                let int_type = IntType {
+                    size: bytes.len(),
+                    unsigned: ty.name.contains("unsigned") || ty.name == "_Bool",
+                };
+                Ok(Scalar::Int(truncate(unit_value(bytes) as i128, int_type), int_type))
+            }
+            TypeKind::Pointer(target) if bytes.len() == size_of::<usize>() => {
+                let size = match target {
+                    Some(target) => self.get_type(*target)?.size,
+                    None => 1,
+                };
+                Ok(Scalar::Pointer(unit_value(bytes), size, value.ty.clone()))
+            }
+            TypeKind::Array { element, .. } if value.addr.is_some() => {
+                let element = self.get_type(*element)?;
+                let size = element.size;
+                let pointer = self.debug_data.pointer_to(&element);
+                Ok(Scalar::Pointer(value.addr.unwrap() as u64, size, pointer))
+            }
+            _ => Err(format!("Invalid operand of type {}.", value.ty.name)),
+        }
+    }
+
+    fn unary(&self, op: &str, value: &Value) -> Result<Value, String> {
+        let scalar = self.scalar(value)?;
+        match (op, scalar) {
+            ("!", _) => Ok(self.bool_value(!self.is_nonzero(value)?)),
+            ("-", Scalar::Int(value, ty)) => Ok(self.int_value(-value, promote(ty))),
+            ("-", Scalar::Float(value)) => Ok(self.float_value(-value)),
+            ("+", Scalar::Int(value, ty)) => Ok(self.int_value(value, promote(ty))),
+            ("+", Scalar::Float(value)) => Ok(self.float_value(value)),
+            ("~", Scalar::Int(value, ty)) => Ok(self.int_value(!value, promote(ty))),
+            _ => Err(format!("Invalid operand of type {} for unary {}.", value.ty.name, op)),
+        }
+    }
+
+    fn binary(&self, op: &str, lhs_value: &Value, rhs_value: &Value) -> Result<Value, String> {
+        let lhs = self.scalar(lhs_value)?;
+        let rhs = self.scalar(rhs_value)?;
+        let pointer_value = |addr: i128, ty: Type| Value {
+            ty,
+            bytes: (addr as u64).to_le_bytes().to_vec(),
+            addr: None,
+            optimized_out: false,
+        };
+        let long = IntType {
+            size: 8,
+            unsigned: false,
+        };
+        let (lhs, rhs) = match (lhs, rhs) {
+            // Pointer arithmetic counts in elements
+            (Scalar::Pointer(addr, size, ty), Scalar::Int(offset, _)) if op == "+" || op == "-" => {
+                let offset = offset * size as i128;
+                let addr = if op == "+" { addr as i128 + offset } else { addr as i128 - offset };
+                return Ok(pointer_value(addr, ty));
+            }
+            (Scalar::Int(offset, _), Scalar::Pointer(addr, size, ty)) if op == "+" => {
+                return Ok(pointer_value(addr as i128 + offset * size as i128, ty));
+            }
+            (Scalar::Pointer(lhs, size, _), Scalar::Pointer(rhs, _, _)) if op == "-" => {
+                let difference = lhs.wrapping_sub(rhs) as i64 as i128;
+                return Ok(self.int_value(difference / size.max(1) as i128, long));
+            }
+            // Otherwise pointers act as unsigned integers, for comparisons and tests against 0
+            (lhs, rhs) => {
+                let as_int = |scalar| match scalar {
+                    Scalar::Pointer(addr, _, _) => Scalar::Int(addr as i128, IntType { size: 8, unsigned: true }),
+                    scalar => scalar,
+                };
+                (as_int(lhs), as_int(rhs))
+            }
+        };
+
+        if let (Scalar::Int(lhs, lhs_type), Scalar::Int(rhs, rhs_type)) = (&lhs, &rhs) {
+            let shift = matches!(op, "<<" | ">>");
+            let ty = if shift { promote(*lhs_type) } else { common_type(*lhs_type, *rhs_type) };
+            let (lhs, rhs) = (truncate(*lhs, ty), if shift { *rhs } else { truncate(*rhs, ty) });
+            let bits = 8 * ty.size as i128;
+            // Like C, results wrap around; int_value truncates them to `ty`
+            let result = match op {
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err("Division by zero".to_string()),
+                "/" => lhs / rhs,
+                "%" => lhs % rhs,
+                "<<" | ">>" if !(0..bits).contains(&rhs) => {
+                    return Err(format!("Shift count {} is out of range.", rhs))
+                }
+                "<<" => lhs << rhs,
+                ">>" => lhs >> rhs,
+                "&" => lhs & rhs,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                _ => return Ok(self.bool_value(compare(op, lhs.cmp(&rhs)))),
+            };
+            return Ok(self.int_value(result, ty));
+        }
+
+        let (lhs, rhs) = (self.as_f64(&lhs), self.as_f64(&rhs));
+        let result = match op {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "==" | "!=" | "<" | "<=" | ">" | ">=" => match lhs.partial_cmp(&rhs) {
+                Some(ordering) => return Ok(self.bool_value(compare(op, ordering))),
+                // Comparisons with NaN are false, except !=
+                None => return Ok(self.bool_value(op == "!=")),
+            },
+            _ => return Err(format!("Integer only operation {}.", op)),
+        };
+        Ok(self.float_value(result))
+    }
+
+    /// Converts a value to type `ty`, as a cast or an assignment does, and returns its bytes.
+    pub fn convert(&self, value: &Value, ty: &Type) -> Result<Vec<u8>, String> {
+        let resolved = self.debug_data.resolve_type(ty);
+        let is_scalar = matches!(resolved.kind, TypeKind::Base | TypeKind::Enum(_) | TypeKind::Pointer(_));
+        if !is_scalar || !(1..=8).contains(&resolved.size) {
+            // Aggregates can only be copied from a value of the same type
+            let source = self.debug_data.resolve_type(&value.ty);
+            if source.name == resolved.name && source.size == resolved.size {
+                return Ok(value.bytes.clone());
+            }
+            return Err(format!("Invalid conversion from {} to {}.", value.ty.name, ty.name));
+        }
+        let scalar = self.scalar(value)?;
+        Ok(match (resolved.name.as_str(), resolved.size, &resolved.kind) {
+            ("float", 4, TypeKind::Base) => (self.as_f64(&scalar) as f32).to_le_bytes().to_vec(),
+            ("double", 8, TypeKind::Base) => self.as_f64(&scalar).to_le_bytes().to_vec(),
+            ("_Bool", _, TypeKind::Base) => vec![self.is_nonzero(value)? as u8],
+            _ => {
+                let value = match scalar {
+                    Scalar::Int(value, _) => value,
+                    Scalar::Float(value) => value as i128,
+                    Scalar::Pointer(addr, _, _) => addr as i128,
+                };
+                value.to_le_bytes()[..resolved.size].to_vec()
+            }
+        })
+    }
+
+    fn as_f64(&self, scalar: &Scalar) -> f64 {
+        match scalar {
+            Scalar::Int(value, _) => *value as f64,
+            Scalar::Float(value) => *value,
+            Scalar::Pointer(addr, _, _) => *addr as f64,
+        }
+    }
+}
+
+fn compare(op: &str, ordering: std::cmp::Ordering) -> bool {
+    use std::cmp::Ordering::*;
+    match op {
+        "==" => ordering == Equal,
+        "!=" => ordering != Equal,
+        "<" => ordering == Less,
+        "<=" => ordering != Greater,
+        ">" => ordering == Greater,
+        _ => ordering != Less,
+    }
+}
+
+impl Value {
+    /// Interprets the value as an address, for commands like `x` that take one. Arrays stand for
+    /// their own address.
+    pub fn as_address(&self, debug_data: &DwarfData) -> Result<usize, String> {
+        if self.optimized_out {
+            return Err("value has been optimized out".to_string());
+        }
+        let ty = debug_data.resolve_type(&self.ty);
+        match ty.kind {
+            TypeKind::Array { .. } if self.addr.is_some() => Ok(self.addr.unwrap()),
+            TypeKind::Base | TypeKind::Enum(_) | TypeKind::Pointer(_) if (1..=8).contains(&self.bytes.len()) => {
+                Ok(unit_value(&self.bytes) as usize)
+            }
+            _ => Err(format!("Value of type {} is not an address.", self.ty.name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const INT: IntType = IntType {
+        size: 4,
+        unsigned: false,
+    };
+
+    fn load_symbols() -> DwarfData {
+        DwarfData::from_file("samples/structs")
+            .expect("Could not read samples/structs. Have you run make?")
+    }
+
+    /// Evaluates an expression without a running program, formatted the way `print` shows it.
+    fn eval(text: &str) -> Result<String, String> {
+        let debug_data = load_symbols();
+        let expression = Expression::parse(text, &debug_data)?;
+        let ctx = Context {
+            debug_data: &debug_data,
+            inferior: None,
+            frame: None,
+        };
+        let value = expression.evaluate(&ctx)?;
+        Ok(debug_data.format_value(&value.ty, &value.bytes, &|_, _| None))
+    }
+
+    fn eval_type(text: &str) -> String {
+        let debug_data = load_symbols();
+        let expression = Expression::parse(text, &debug_data).unwrap();
+        let ctx = Context {
+            debug_data: &debug_data,
+            inferior: None,
+            frame: None,
+        };
+        expression.evaluate(&ctx).unwrap().ty.name
+    }
+
+    #[test]
+    fn test_tokenize_numbers() {
+        assert_eq!(tokenize("42").unwrap(), vec![Token::Integer(42, INT)]);
+        assert_eq!(tokenize("0x1f").unwrap(), vec![Token::Integer(31, INT)]);
+        assert_eq!(tokenize("010").unwrap(), vec![Token::Integer(8, INT)]);
+        assert_eq!(tokenize("1.5").unwrap(), vec![Token::Float(1.5)]);
+        assert_eq!(tokenize(".5f").unwrap(), vec![Token::Float(0.5)]);
+        assert_eq!(tokenize("1e3").unwrap(), vec![Token::Float(1000.0)]);
+        assert_eq!(tokenize("2.5e-1").unwrap(), vec![Token::Float(0.25)]);
+    }
+
+    #[test]
+    fn test_tokenize_literal_suffixes() {
+        let unsigned = IntType {
+            size: 4,
+            unsigned: true,
+        };
+        let long = IntType {
+            size: 8,
+            unsigned: false,
+        };
+        assert_eq!(tokenize("7u").unwrap(), vec![Token::Integer(7, unsigned)]);
+        assert_eq!(tokenize("7L").unwrap(), vec![Token::Integer(7, long)]);
+        assert_eq!(tokenize("4294967296").unwrap(), vec![Token::Integer(1 << 32, long)]);
+    }
+
+    #[test]
+    fn test_tokenize_hex_exponent_sign() {
+        // The e is a hex digit, so the + is an operator
+        assert_eq!(
+            tokenize("0x1e+5").unwrap(),
+            vec![Token::Integer(30, INT), Token::Punct("+"), Token::Integer(5, INT)]
+        );
+        assert_eq!(
+            tokenize("0XE-1").unwrap(),
+            vec![Token::Integer(14, INT), Token::Punct("-"), Token::Integer(1, INT)]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_words_and_punctuation() {
+        assert_eq!(
+            tokenize("p->next[0] <= $rip").unwrap(),
+            vec![
+                Token::Identifier("p".to_string()),
+                Token::Punct("->"),
+                Token::Identifier("next".to_string()),
+                Token::Punct("["),
+                Token::Integer(0, INT),
+                Token::Punct("]"),
+                Token::Punct("<="),
+                Token::Register("rip".to_string()),
+            ]
+        );
+        assert_eq!(tokenize("'a' '\\n'").unwrap(), vec![Token::Char(b'a'), Token::Char(b'\n')]);
+    }
+
+    #[test]
+    fn test_tokenize_errors() {
+        assert!(tokenize("1 @ 2").is_err());
+        assert!(tokenize("'a").is_err());
+        assert!(tokenize("'\\q'").is_err());
+        assert!(tokenize("0x").is_err());
+        assert!(tokenize("1.2.3").is_err());
+        assert!(tokenize("09").is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("1 + 2").unwrap(), "3");
+        assert_eq!(eval("0x1e+5").unwrap(), "35");
+        assert_eq!(eval("7 / 2").unwrap(), "3");
+        assert_eq!(eval("-7 % 3").unwrap(), "-1");
+        assert_eq!(eval("1 << 4 | 1").unwrap(), "17");
+        assert_eq!(eval("~0").unwrap(), "-1");
+        assert_eq!(eval("'a' + 1").unwrap(), "98");
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("2 + 3 * 4").unwrap(), "14");
+        assert_eq!(eval("(2 + 3) * 4").unwrap(), "20");
+        assert_eq!(eval("10 - 4 - 3").unwrap(), "3");
+        assert_eq!(eval("1 + 2 == 3").unwrap(), "1");
+        assert_eq!(eval("1 < 2 && 2 < 1 || 1").unwrap(), "1");
+        assert_eq!(eval("-2 * -3").unwrap(), "6");
+        assert_eq!(eval("!0 + 1").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_integer_overflow_wraps() {
+        assert_eq!(eval("0xffffffffffffffff * 0xffffffffffffffff").unwrap(), "1");
+        assert_eq!(eval("0xffffffffffffffff + 2").unwrap(), "1");
+        assert_eq!(eval("0ul - 1").unwrap(), "18446744073709551615");
+        assert_eq!(eval("2147483647 + 1").unwrap(), "-2147483648");
+        assert_eq!(eval("65536 * 65536").unwrap(), "0");
+    }
+
+    #[test]
+    fn test_usual_arithmetic_conversions() {
+        assert_eq!(eval_type("1 + 1"), "int");
+        assert_eq!(eval_type("1 + 1u"), "unsigned int");
+        assert_eq!(eval_type("1u + 1L"), "long int");
+        assert_eq!(eval_type("1 + 0.5"), "double");
+        // -1 becomes the largest unsigned int when compared with one
+        assert_eq!(eval("-1 < 1u").unwrap(), "0");
+    }
+
+    #[test]
+    fn test_casts() {
+        assert_eq!(eval("(char)300").unwrap(), "44 ','");
+        assert_eq!(eval("(unsigned char)-1").unwrap(), "255 '\\377'");
+        assert_eq!(eval("(int)2.9").unwrap(), "2");
+        assert_eq!(eval("(double)1 / 4").unwrap(), "0.25");
+        assert_eq!(eval_type("(unsigned long)1"), "long unsigned int");
+        assert_eq!(eval_type("(struct node *)0"), "struct node *");
+        assert_eq!(eval_type("(color_t)1"), "color_t");
+        // Parentheses around an expression aren't a cast
+        assert_eq!(eval("(3)").unwrap(), "3");
+    }
+
+    #[test]
+    fn test_member_access_needs_the_program() {
+        assert_eq!(
+            eval("((struct node *)0)->value").unwrap_err(),
+            "The program is not being run."
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let debug_data = load_symbols();
+        let parse = |text| Expression::parse(text, &debug_data).map(|_| ());
+        assert_eq!(parse("").unwrap_err(), "Missing expression");
+        assert_eq!(parse("1 +").unwrap_err(), "Unexpected end of expression.");
+        assert_eq!(parse("(1 + 2").unwrap_err(), "Expected `)' in expression.");
+        assert_eq!(parse("1 2").unwrap_err(), "A syntax error in expression, near `2'.");
+        assert_eq!(parse("p.").unwrap_err(), "Expected a field name after `.'.");
+        assert_eq!(parse("(struct nosuch *)0").unwrap_err(), "No struct nosuch.");
+        assert_eq!(parse("(void)0").unwrap_err(), "Invalid cast to void.");
+        assert_eq!(parse("(unsigned signed)0").unwrap_err(), "Invalid type \"unsigned signed\".");
+    }
+
+    #[test]
+    fn test_evaluation_errors() {
+        assert_eq!(eval("1 / 0").unwrap_err(), "Division by zero");
+        assert_eq!(eval("1.5 % 2").unwrap_err(), "Integer only operation %.");
+        assert_eq!(eval("1 << 64").unwrap_err(), "Shift count 64 is out of range.");
+        assert_eq!(eval("*1").unwrap_err(), "Attempt to take contents of a non-pointer value.");
+        assert_eq!(eval("nosuch").unwrap_err(), "No symbol \"nosuch\" in current context.");
+        assert_eq!(eval("$rip").unwrap_err(), "The program has no registers now.");
+    }
+}
@@ -5,18 +5,25 @@
 //!
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
-use gimli;
 use gimli::{UnitOffset, UnitSectionOffset};
 use object::{Object, ObjectSection};
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    File, Function, Line, Location, Member, Scope, Type, TypeId, TypeKind, Variable,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
+use std::mem::size_of;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+/// Reads the compilation units and the types they define. Types are keyed by the offset of their
+/// DIE in .debug_info.
+pub fn load_file(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<(Vec<File>, HashMap<TypeId, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         match object.section_by_name(id.name()) {
@@ -37,13 +44,13 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     let borrow_section: &dyn for<'a> Fn(
         &'a borrow::Cow<[u8]>,
     ) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-        &|section| gimli::EndianSlice::new(&*section, endian);
+        &|section| gimli::EndianSlice::new(section, endian);
 
     // Create `EndianSlice`s for all of the sections.
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
-    // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    // Types are read up front, since variables may refer to types defined after them
+    let offset_to_type = load_types(&dwarf)?;
 
     let mut compilation_units: Vec<File> = Vec::new();
 
@@ -54,10 +61,14 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
 
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
+        // Functions and scopes whose DIEs are being read, innermost last
+        let mut open_scopes: Vec<OpenScope> = Vec::new();
         let mut entries = unit.entries();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
-            // Update the offset_to_type mapping for types
+            while open_scopes.last().is_some_and(|open| depth <= open.depth) {
+                close_scope(&mut open_scopes, compilation_units.last_mut().unwrap());
+            }
             // Update the variable list for formal params/variables
             match entry.tag() {
                 gimli::DW_TAG_compile_unit => {
@@ -70,42 +81,22 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     } else {
                         "<unknown>".to_string()
                     };
+                    // Relative names are relative to the compilation directory
+                    let path = match unit.comp_dir {
+                        Some(ref dir) => path::Path::new(dir.to_string_lossy().as_ref()).join(&name),
+                        None => path::PathBuf::from(&name),
+                    };
                     compilation_units.push(File {
                         name,
+                        path: path.to_string_lossy().into_owned(),
                         global_variables: Vec::new(),
                         functions: Vec::new(),
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
+                    let mut is_declaration = false;
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
@@ -132,16 +123,68 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_frame_base => {
+                                func.frame_base = get_location(&attr, &unit, &dwarf)?;
+                            }
+                            gimli::DW_AT_declaration => is_declaration = true,
                             _ => {}
                         }
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
+                    // Out-of-line copies of inlined functions leave the name to the abstract
+                    // instance of the function
+                    if func.name.is_empty() {
+                        if let Some(origin) = abstract_origin(entry, &unit)? {
+                            func.name = entry_name(&origin, &unit, &dwarf).unwrap_or_default();
+                            func.line_number = decl_line(&origin)?;
+                        }
+                    }
+                    // Functions split into several parts have DW_AT_ranges instead of DW_AT_low_pc
+                    if func.address == 0 {
+                        if let Some((start, end)) = get_ranges(entry, &unit, &dwarf)?.first() {
+                            func.address = *start;
+                            func.text_length = end - start;
+                        }
+                    }
+                    // Prototypes of functions defined elsewhere (e.g. printf) and the abstract
+                    // instances of inlined functions have no code
+                    if !is_declaration && func.address != 0 {
+                        let file = compilation_units.last_mut().unwrap();
+                        file.functions.push(func);
+                        open_scopes.push(OpenScope {
+                            depth,
+                            function: Some(file.functions.len() - 1),
+                            scope: Scope::default(),
+                        });
+                    }
+                }
+                // Blocks in the abstract instances of inlined functions have no code, so only
+                // the ones in functions are read
+                gimli::DW_TAG_lexical_block | gimli::DW_TAG_inlined_subroutine
+                    if !open_scopes.is_empty() =>
+                {
+                    let inlined_function = if entry.tag() == gimli::DW_TAG_inlined_subroutine {
+                        let origin = abstract_origin(entry, &unit)?;
+                        let name = origin.and_then(|origin| entry_name(&origin, &unit, &dwarf));
+                        Some(name.unwrap_or_else(|| "??".to_string()))
+                    } else {
+                        None
+                    };
+                    open_scopes.push(OpenScope {
+                        depth,
+                        function: None,
+                        scope: Scope {
+                            inlined_function,
+                            ranges: get_ranges(entry, &unit, &dwarf)?,
+                            ..Default::default()
+                        },
+                    });
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
                     let mut name = String::new();
                     let mut entity_type: Option<Type> = None;
                     let mut location: Option<Location> = None;
                     let mut line_number = 0;
+                    let mut is_declaration = false;
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
@@ -154,29 +197,48 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             }
                             gimli::DW_AT_type => {
                                 if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
+                                    if let Some(dtype) = offset_to_type.get(&offset) {
                                         entity_type = Some(dtype.clone());
                                     }
                                 }
                             }
-                            gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
-                                }
-                            }
+                            gimli::DW_AT_location => location = get_location(&attr, &unit, &dwarf)?,
+                            gimli::DW_AT_const_value => location = get_const_value(&attr),
                             gimli::DW_AT_decl_line => {
                                 if let Ok(DebugValue::Uint(num)) = val {
                                     line_number = num;
                                 }
                             }
+                            gimli::DW_AT_declaration => is_declaration = true,
                             _ => {}
                         }
                     }
-                    if entity_type.is_some() && location.is_some() {
+                    // Variables of inlined functions take their name and type from the abstract
+                    // instance of the function
+                    if name.is_empty() || entity_type.is_none() {
+                        if let Some(origin) = abstract_origin(entry, &unit)? {
+                            if name.is_empty() {
+                                name = entry_name(&origin, &unit, &dwarf).unwrap_or_default();
+                                line_number = decl_line(&origin)? as u64;
+                            }
+                            if entity_type.is_none() {
+                                entity_type = type_ref(&origin, &unit)?
+                                    .and_then(|id| offset_to_type.get(&id))
+                                    .cloned();
+                            }
+                        }
+                    }
+                    // Declarations of variables defined elsewhere have no location of their own,
+                    // and the parameters of prototypes have no name
+                    let is_prototype_param = name.is_empty();
+                    if let (Some(entity_type), false, false) =
+                        (entity_type, is_declaration, is_prototype_param)
+                    {
                         let var = Variable {
                             name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
+                            entity_type,
+                            // Optimized code may leave out the location of variables it got rid of
+                            location: location.unwrap_or(Location::OptimizedOut),
                             line_number: line_number.try_into().unwrap(),
                         };
                         if depth == 1 {
@@ -185,15 +247,8 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                 .unwrap()
                                 .global_variables
                                 .push(var);
-                        } else if depth > 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .functions
-                                .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                        } else if let Some(open) = open_scopes.last_mut() {
+                            open.scope.variables.push(var);
                         }
                     }
                 }
@@ -202,6 +257,9 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                 _ => {}
             }
         }
+        while !open_scopes.is_empty() {
+            close_scope(&mut open_scopes, compilation_units.last_mut().unwrap());
+        }
 
         // Get line numbers
         if let Some(program) = unit.line_program.clone() {
@@ -223,10 +281,14 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         );
                     }
 
-                    // Get the File
+                    // Get the File. DWARF 5 names files in the compilation directory by their
+                    // absolute path, while the compilation unit name is relative to it.
                     let file = compilation_units
                         .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                        .find(|f| {
+                            f.name == path.as_os_str().to_str().unwrap()
+                                || path::Path::new(&f.path) == path
+                        });
 
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
@@ -247,10 +309,332 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, offset_to_type))
+}
+
+/// A function, lexical block or inlined call whose DIEs are being read
+struct OpenScope {
+    depth: isize,
+    /// Index of the function in its file, if this is a function's outermost scope
+    function: Option<usize>,
+    scope: Scope,
+}
+
+/// Finishes reading the innermost open scope, and adds it to its function or enclosing scope.
+fn close_scope(open_scopes: &mut Vec<OpenScope>, file: &mut File) {
+    let open = open_scopes.pop().unwrap();
+    if let Some(index) = open.function {
+        let func = &mut file.functions[index];
+        func.variables = open.scope.variables;
+        func.scopes = open.scope.scopes;
+    } else if let Some(parent) = open_scopes.last_mut() {
+        parent.scope.scopes.push(open.scope);
+    }
+}
+
+/// Bound on how deeply type names and sizes are followed, in case of malformed cyclic DWARF
+const MAX_TYPE_DEPTH: usize = 32;
+
+/// Reads every type DIE into a graph keyed by .debug_info offset, then works out the names and
+/// sizes of the types, which can depend on types defined further on.
+fn load_types<R: Reader>(dwarf: &gimli::Dwarf<R>) -> Result<HashMap<TypeId, Type>, Error> {
+    let mut types: HashMap<TypeId, Type> = HashMap::new();
+    // Dimensions of each array type: the subrange DIE and the element count it gives
+    let mut array_dims: HashMap<TypeId, Vec<(TypeId, Option<usize>)>> = HashMap::new();
+
+    let mut iter = dwarf.units();
+    while let Some(header) = iter.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut depth = 0;
+        // Type DIEs enclosing the current one, which members, enumerators and subranges belong to
+        let mut parents: Vec<(isize, TypeId)> = Vec::new();
+        let mut entries = unit.entries();
+        while let Some((delta_depth, entry)) = entries.next_dfs()? {
+            depth += delta_depth;
+            while parents.last().is_some_and(|(parent_depth, _)| *parent_depth >= depth) {
+                parents.pop();
+            }
+            let parent = parents
+                .last()
+                .filter(|(parent_depth, _)| *parent_depth == depth - 1)
+                .map(|(_, id)| *id);
+            let id = die_offset(&unit, entry.offset());
+            let name = entry_name(entry, &unit, dwarf);
+            let target = type_ref(entry, &unit)?;
+            let byte_size = entry
+                .attr(gimli::DW_AT_byte_size)?
+                .and_then(|attr| attr.udata_value())
+                .map(|size| size as usize);
+
+            let kind = match entry.tag() {
+                gimli::DW_TAG_base_type => TypeKind::Base,
+                gimli::DW_TAG_pointer_type => TypeKind::Pointer(target),
+                gimli::DW_TAG_structure_type => TypeKind::Struct(Vec::new()),
+                gimli::DW_TAG_union_type => TypeKind::Union(Vec::new()),
+                gimli::DW_TAG_enumeration_type => TypeKind::Enum(Vec::new()),
+                gimli::DW_TAG_typedef => TypeKind::Typedef(target),
+                gimli::DW_TAG_const_type => TypeKind::Qualified("const", target),
+                gimli::DW_TAG_volatile_type => TypeKind::Qualified("volatile", target),
+                gimli::DW_TAG_restrict_type => TypeKind::Qualified("restrict", target),
+                gimli::DW_TAG_subroutine_type => TypeKind::Function(target),
+                gimli::DW_TAG_array_type => match target {
+                    Some(element) => TypeKind::Array {
+                        element,
+                        count: None,
+                    },
+                    None => continue,
+                },
+                gimli::DW_TAG_member => {
+                    let offset = entry
+                        .attr(gimli::DW_AT_data_member_location)?
+                        .and_then(|attr| attr.udata_value())
+                        .unwrap_or(0) as usize;
+                    if let (Some(parent), Some(type_id)) = (parent, target) {
+                        if let Some(Type {
+                            kind: TypeKind::Struct(members) | TypeKind::Union(members),
+                            ..
+                        }) = types.get_mut(&parent)
+                        {
+                            members.push(Member {
+                                name: name.unwrap_or_default(),
+                                type_id,
+                                offset,
+                            });
+                        }
+                    }
+                    continue;
+                }
+                gimli::DW_TAG_enumerator => {
+                    let value = match entry.attr(gimli::DW_AT_const_value)? {
+                        Some(attr) => match attr.value() {
+                            gimli::AttributeValue::Sdata(value) => value,
+                            _ => attr.udata_value().unwrap_or(0) as i64,
+                        },
+                        None => 0,
+                    };
+                    if let Some(Type {
+                        kind: TypeKind::Enum(enumerators),
+                        ..
+                    }) = parent.and_then(|parent| types.get_mut(&parent))
+                    {
+                        enumerators.push((name.unwrap_or_default(), value));
+                    }
+                    continue;
+                }
+                gimli::DW_TAG_subrange_type => {
+                    // Arrays give either the number of elements or the highest index. Neither is
+                    // there for arrays of unknown length, and VLAs compute it at runtime.
+                    let count = match entry.attr(gimli::DW_AT_count)? {
+                        Some(attr) => attr.udata_value().map(|count| count as usize),
+                        None => entry
+                            .attr(gimli::DW_AT_upper_bound)?
+                            .and_then(|attr| attr.udata_value())
+                            .map(|bound| bound as usize + 1),
+                    };
+                    if let Some(parent) = parent {
+                        array_dims.entry(parent).or_default().push((id, count));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let size = match kind {
+                TypeKind::Pointer(_) => byte_size.unwrap_or(size_of::<usize>()),
+                _ => byte_size.unwrap_or(0),
+            };
+            types.insert(
+                id,
+                Type {
+                    name: name.unwrap_or_default(),
+                    size,
+                    kind,
+                    id: Some(id),
+                },
+            );
+            parents.push((depth, id));
+        }
+    }
+
+    // A multi-dimensional array like `int a[2][3]` is a single DIE with a subrange per
+    // dimension. Model it as an array of arrays, using the subrange DIEs for the inner ones.
+    for (id, dims) in array_dims {
+        let mut element = match types.get(&id) {
+            Some(Type {
+                kind: TypeKind::Array { element, .. },
+                ..
+            }) => *element,
+            _ => continue,
+        };
+        for (index, (subrange, count)) in dims.into_iter().enumerate().rev() {
+            let array_id = if index == 0 { id } else { subrange };
+            types.insert(
+                array_id,
+                Type {
+                    name: String::new(),
+                    size: 0,
+                    kind: TypeKind::Array { element, count },
+                    id: Some(array_id),
+                },
+            );
+            element = array_id;
+        }
+    }
+
+    Ok(types
+        .iter()
+        .map(|(id, ty)| {
+            let resolved = Type {
+                name: type_name(&types, *id, 0),
+                size: type_size(&types, *id, 0),
+                kind: ty.kind.clone(),
+                id: Some(*id),
+            };
+            (*id, resolved)
+        })
+        .collect())
+}
+
+/// Spells out a type the way C declares it, e.g. "struct node *" or "int [2][3]".
+fn type_name(types: &HashMap<TypeId, Type>, id: TypeId, depth: usize) -> String {
+    let ty = match types.get(&id) {
+        Some(ty) if depth < MAX_TYPE_DEPTH => ty,
+        _ => return "<unknown type>".to_string(),
+    };
+    let target_name = |target: &Option<TypeId>| match target {
+        Some(target) => type_name(types, *target, depth + 1),
+        None => "void".to_string(),
+    };
+    let tagged = |tag: &str| match ty.name.as_str() {
+        "" => format!("{} {{...}}", tag),
+        name => format!("{} {}", tag, name),
+    };
+    match &ty.kind {
+        TypeKind::Base | TypeKind::Typedef(_) => ty.name.clone(),
+        TypeKind::Struct(_) => tagged("struct"),
+        TypeKind::Union(_) => tagged("union"),
+        TypeKind::Enum(_) => tagged("enum"),
+        TypeKind::Pointer(target) => match target.and_then(|target| types.get(&target)) {
+            Some(Type {
+                kind: TypeKind::Function(ret),
+                ..
+            }) => format!("{} (*)()", target_name(ret)),
+            _ => {
+                let name = target_name(target);
+                if name.ends_with('*') {
+                    format!("{}*", name)
+                } else {
+                    format!("{} *", name)
+                }
+            }
+        },
+        TypeKind::Array { element, count } => {
+            let dim = match count {
+                Some(count) => format!("[{}]", count),
+                None => "[]".to_string(),
+            };
+            // The outer dimension comes first: an array of `int [3]` is `int [2][3]`
+            let element = type_name(types, *element, depth + 1);
+            match element.find(" [") {
+                Some(index) => format!("{} {}{}", &element[..index], dim, &element[index + 1..]),
+                None => format!("{} {}", element, dim),
+            }
+        }
+        TypeKind::Qualified(qualifier, target) => {
+            let name = target_name(target);
+            if name.ends_with('*') {
+                format!("{} {}", name, qualifier)
+            } else {
+                format!("{} {}", qualifier, name)
+            }
+        }
+        TypeKind::Function(ret) => format!("{} ()", target_name(ret)),
+    }
+}
+
+fn type_size(types: &HashMap<TypeId, Type>, id: TypeId, depth: usize) -> usize {
+    let ty = match types.get(&id) {
+        Some(ty) if depth < MAX_TYPE_DEPTH => ty,
+        _ => return 0,
+    };
+    match &ty.kind {
+        TypeKind::Array { element, count } => {
+            count.unwrap_or(0) * type_size(types, *element, depth + 1)
+        }
+        TypeKind::Typedef(target) | TypeKind::Qualified(_, target) => {
+            target.map_or(0, |target| type_size(types, target, depth + 1))
+        }
+        _ => ty.size,
+    }
+}
+
+fn die_offset<R: Reader>(unit: &gimli::Unit<R>, offset: UnitOffset) -> TypeId {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+fn entry_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Returns the DIE an entry's DW_AT_abstract_origin refers to. The concrete copies of inlined
+/// functions and their variables leave their name and type to that DIE.
+fn abstract_origin<'unit, R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &'unit gimli::Unit<R>,
+) -> Result<Option<gimli::DebuggingInformationEntry<'unit, 'unit, R>>, Error> {
+    Ok(match entry.attr_value(gimli::DW_AT_abstract_origin)? {
+        Some(gimli::AttributeValue::UnitRef(offset)) => Some(unit.entry(offset)?),
+        _ => None,
+    })
+}
+
+fn decl_line<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>) -> Result<usize, Error> {
+    let line = entry
+        .attr_value(gimli::DW_AT_decl_line)?
+        .and_then(|value| value.udata_value());
+    Ok(line.unwrap_or(0).try_into().unwrap())
+}
+
+/// Returns the address ranges of a function's or scope's code, given by either DW_AT_low_pc and
+/// DW_AT_high_pc or DW_AT_ranges.
+fn get_ranges<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<Vec<(usize, usize)>, Error> {
+    let mut ranges = Vec::new();
+    let mut iter = dwarf.die_ranges(unit, entry)?;
+    while let Some(range) = iter.next()? {
+        if range.begin < range.end {
+            ranges.push((range.begin.try_into().unwrap(), range.end.try_into().unwrap()));
+        }
+    }
+    Ok(ranges)
+}
+
+/// Returns the .debug_info offset of the type an entry's DW_AT_type refers to.
+fn type_ref<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+) -> Result<Option<TypeId>, Error> {
+    Ok(match entry.attr_value(gimli::DW_AT_type)? {
+        Some(gimli::AttributeValue::UnitRef(offset)) => Some(die_offset(unit, offset)),
+        Some(gimli::AttributeValue::DebugInfoRef(offset)) => Some(offset.0),
+        _ => None,
+    })
 }
 
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub enum DebugValue {
     Str(String),
     Uint(u64),
@@ -260,6 +644,7 @@ pub enum DebugValue {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     GimliError(gimli::Error),
     // Addr2lineError(addr2line::gimli::Error),
@@ -298,25 +683,75 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+/// Reads a DW_AT_location or DW_AT_frame_base, which is either a single expression or a list of
+/// expressions for different ranges of pcs.
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<Option<Location>, Error> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
-        let encoding = unit.encoding();
-        let mut pc = data.0.clone();
-        if pc.len() > 0 {
-            if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
-                match op {
-                    gimli::Operation::FrameOffset { offset } => {
-                        return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
-                    }
-                    gimli::Operation::Address { address } => {
-                        return Some(Location::Address(address.try_into().unwrap()));
-                    }
-                    _ => {}
-                }
-            }
+        return Ok(Some(expression_location(data, unit.encoding())?));
+    }
+    let offset = match dwarf.attr_locations_offset(unit, attr.value())? {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+    let mut entries = Vec::new();
+    let mut locations = dwarf.locations(unit, offset)?;
+    while let Some(entry) = locations.next()? {
+        entries.push((
+            entry.range.begin.try_into().unwrap(),
+            entry.range.end.try_into().unwrap(),
+            expression_location(&entry.data, unit.encoding())?,
+        ));
+    }
+    Ok(Some(Location::List(entries)))
+}
+
+/// gcc's marker for a variable that isn't initialized yet, which gimli doesn't know
+const DW_OP_GNU_UNINIT: u8 = 0xf0;
+
+/// Recognizes the lone DW_OP_fbreg and DW_OP_addr that unoptimized code uses, and keeps any other
+/// expression to be evaluated when the variable is read.
+fn expression_location<R: Reader>(
+    expression: &gimli::Expression<R>,
+    encoding: gimli::Encoding,
+) -> Result<Location, Error> {
+    let mut bytes = expression.0.to_slice()?.into_owned();
+    let mut pc = expression.0.clone();
+    let mut ops = Vec::new();
+    while !pc.is_empty() {
+        if pc.len() == 1 && pc.clone().read_u8()? == DW_OP_GNU_UNINIT {
+            bytes.pop();
+            break;
         }
+        match gimli::Operation::parse(&mut pc, encoding) {
+            Ok(op) => ops.push(op),
+            Err(_) => break,
+        }
+    }
+    match ops.as_slice() {
+        [] if bytes.is_empty() => Ok(Location::OptimizedOut),
+        [gimli::Operation::FrameOffset { offset }] if pc.is_empty() => {
+            Ok(Location::FramePointerOffset((*offset).try_into().unwrap()))
+        }
+        [gimli::Operation::Address { address }] if pc.is_empty() => {
+            Ok(Location::Address((*address).try_into().unwrap()))
+        }
+        _ => Ok(Location::Expression(bytes, encoding)),
+    }
+}
+
+/// Reads a DW_AT_const_value as the little-endian bytes of the constant.
+fn get_const_value<R: Reader>(attr: &gimli::Attribute<R>) -> Option<Location> {
+    if let gimli::AttributeValue::Block(data) = attr.value() {
+        return Some(Location::Constant(data.to_slice().ok()?.into_owned()));
     }
-    None
+    let value = attr
+        .udata_value()
+        .or_else(|| attr.sdata_value().map(|value| value as u64))?;
+    Some(Location::Constant(value.to_le_bytes().to_vec()))
 }
 
 // based on dwarf_dump.rs
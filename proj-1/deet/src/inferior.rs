@@ -1,23 +1,26 @@
-use libc::SECCOMP_ADDFD_FLAG_SEND;
 use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
+use nix::sys::uio::{process_vm_readv, RemoteIoVec};
 use std::collections::HashMap;
-use std::convert::TryInto;
-use std::hash::Hash;
+use std::io::IoSliceMut;
 use std::process::Command;
 use std::os::unix::process::CommandExt;
-use std::process::Child;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
+use crate::dwarf_data::DwarfData;
+use crate::error::DebuggerError;
+use crate::signals::SignalTable;
+use crate::unwind::{Frame, Registers, Unwinder};
+use std::cell::RefCell;
+use std::path::PathBuf;
 
-use crate::inferior;
 use std::mem::size_of;
 
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
+#[derive(Debug)]
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
     /// current instruction pointer that it is stopped at.
@@ -31,23 +34,201 @@ pub enum Status {
     Signaled(signal::Signal),
 }
 
+/// Something that happened to the process while it ran, which the debugger should hear about.
+/// Collected by `take_events`.
+pub enum ProcessEvent {
+    /// The process forked, and the child is kept stopped as another inferior
+    Forked(Inferior),
+    /// The process forked and the debugger should switch to the child, which is stopped
+    FollowFork(Inferior),
+    /// The process forked, and the child was detached
+    DetachedFork(Pid),
+    /// The process exec'd the program at this path
+    Exec(String),
+    /// A thread was created. Contains its number and thread id.
+    NewThread(usize, Pid),
+    /// A thread exited. Contains its number and thread id.
+    ThreadExited(usize, Pid),
+}
+
+/// A thread of the inferior.
+pub struct Thread {
+    /// Number of the thread, as shown by `info threads`
+    pub id: usize,
+    pub tid: Pid,
+    /// Whether the thread was resumed and hasn't been seen stopping since
+    running: bool,
+    /// Whether a SIGSTOP deet sent to stop the thread is still to come, and must be swallowed
+    stop_pending: bool,
+    /// A signal the thread received while it was being stopped, delivered when it resumes
+    pending_signal: Option<signal::Signal>,
+}
+
+impl Thread {
+    fn new(id: usize, tid: Pid) -> Thread {
+        Thread {
+            id,
+            tid,
+            running: false,
+            stop_pending: false,
+            pending_signal: None,
+        }
+    }
+}
+
+/// How forks are handled, set with `set follow-fork-mode` and `set detach-on-fork`.
+#[derive(Clone, Copy)]
+pub struct ForkPolicy {
+    /// Whether the debugger goes on with the child rather than the parent
+    pub follow_child: bool,
+    /// Whether the process that isn't followed is detached, rather than kept as another inferior
+    pub detach: bool,
+}
+
+impl Default for ForkPolicy {
+    fn default() -> Self {
+        ForkPolicy {
+            follow_child: false,
+            detach: true,
+        }
+    }
+}
+
+/// How a resume function asks the process to run.
+#[derive(Clone, Copy)]
+enum Request {
+    Continue,
+    Step,
+}
+
+/// Why the inferior trapped, as passed to the `stop_at` callbacks of the resume functions.
+pub enum Trap {
+    /// The inferior hit the breakpoint at this address
+    Breakpoint(usize),
+    /// The inferior wrote to memory watched by these debug register slots
+    Watchpoint(Vec<usize>),
+}
+
+/// Offset of debug register `index` in the user area accessed by PTRACE_PEEKUSER/POKEUSER
+fn debug_register_offset(index: usize) -> ptrace::AddressType {
+    (std::mem::offset_of!(libc::user, u_debugreg) + index * size_of::<u64>()) as ptrace::AddressType
+}
+
+/// Programs debug register `slot` of thread `tid`, as `Inferior::set_hw_watchpoint` describes.
+fn set_thread_watchpoint(
+    tid: Pid,
+    slot: usize,
+    watch: Option<(usize, usize)>,
+) -> Result<(), DebuggerError> {
+    let mut dr7 = ptrace::read_user(tid, debug_register_offset(7))? as u64;
+    // Local enable bit, followed by the RW and LEN fields of the slot
+    dr7 &= !(0b1 << (2 * slot)) & !(0b1111 << (16 + 4 * slot));
+    if let Some((addr, len)) = watch {
+        let len_bits = match len {
+            1 => 0b00,
+            2 => 0b01,
+            8 => 0b10,
+            _ => 0b11,
+        };
+        unsafe {
+            ptrace::write_user(tid, debug_register_offset(slot), addr as *mut std::ffi::c_void)?;
+        }
+        dr7 |= (0b1 << (2 * slot)) | (((len_bits << 2) | 0b01) << (16 + 4 * slot));
+    }
+    unsafe { ptrace::write_user(tid, debug_register_offset(7), dr7 as *mut std::ffi::c_void)? };
+    Ok(())
+}
+
+/// Gives thread `to` the same watchpoints as thread `from`. New threads start out without any.
+fn copy_watchpoints(from: Pid, to: Pid) -> Result<(), DebuggerError> {
+    for index in (0..NUM_WATCHPOINT_SLOTS).chain([7]) {
+        let value = ptrace::read_user(from, debug_register_offset(index))?;
+        unsafe {
+            ptrace::write_user(to, debug_register_offset(index), value as *mut std::ffi::c_void)?;
+        }
+    }
+    Ok(())
+}
+
+/// Number of x86-64 debug registers (DR0-DR3) that can hold a watched address
+pub const NUM_WATCHPOINT_SLOTS: usize = 4;
+
+/// Backtraces stop after this many frames, in case the stack is corrupt
+pub const MAX_FRAMES: usize = 256;
+
+/// Registers shown by `info registers`, in the order they are printed
+pub const REGISTER_NAMES: [&str; 24] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+    "r13", "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs",
+];
+
+/// Returns the field of `regs` holding the register `name`. `pc`, `sp` and `fp` are accepted as
+/// aliases for `rip`, `rsp` and `rbp`.
+pub fn register_field<'a>(regs: &'a mut libc::user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match name {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" | "fp" => &mut regs.rbp,
+        "rsp" | "sp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" | "pc" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        _ => return None,
+    })
+}
+
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
-    ptrace::traceme().or(Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "ptrace TRACEME failed",
-    )))
+    ptrace::traceme().or(Err(std::io::Error::other("ptrace TRACEME failed")))
 }
 
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    /// The current thread, which registers are read from and which is stepped
+    tid: Pid,
+    threads: Vec<Thread>,
+    next_thread_id: usize,
+    /// Threads that reported their first stop before their creator reported the clone
+    early_threads: Vec<Pid>,
+    /// Whether deet attached to an already running process rather than starting it
+    attached: bool,
+    /// Caches the unwind tables of the files mapped into the process
+    unwinder: RefCell<Unwinder>,
+    fork_policy: ForkPolicy,
+    signals: SignalTable,
+    /// Path of the program being debugged, whose breakpoints are written into the process
+    program: PathBuf,
+    /// Whether the process exec'd some other program, to which the breakpoints don't apply
+    other_program: bool,
+    /// Events not yet collected by `take_events`
+    events: Vec<ProcessEvent>,
 }
 
 impl Inferior {
-    /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, break_list: &mut HashMap<usize, u8>) -> Option<Inferior> {
+    /// Attempts to start a new inferior process, stopped at its first instruction.
+    pub fn new(
+        target: &str,
+        args: &[String],
+        break_list: &mut HashMap<usize, u8>,
+    ) -> Result<Inferior, DebuggerError> {
         // println!("{:?}, {:?}", target, args);
         let mut cmd = Command::new(target);
         cmd.args(args);
@@ -55,100 +236,867 @@ impl Inferior {
             cmd.pre_exec(child_traceme);
         }
 
-        let child = cmd.spawn().ok()?;
-        let mut inferior = Inferior { child: child };
+        let child = cmd.spawn().map_err(DebuggerError::Spawn)?;
+        let mut inferior = Inferior::traced(Pid::from_raw(child.id() as i32), false);
+        // The child stops with SIGTRAP once it execs the target; breakpoints can only be written
+        // after that.
+        match inferior.wait(None)? {
+            Status::Stopped(signal::Signal::SIGTRAP, _) => {}
+            other => return Err(DebuggerError::UnexpectedStatus(format!("{:?}", other))),
+        }
+        inferior.trace_children()?;
+        inferior.insert_breakpoints(break_list);
+        Ok(inferior)
+    }
+
+    /// Attaches to the running process `pid` with PTRACE_ATTACH and writes the breakpoints into
+    /// it. The process is left stopped.
+    pub fn attach(pid: Pid, break_list: &mut HashMap<usize, u8>) -> Result<Inferior, DebuggerError> {
+        let mut inferior = Inferior::traced(pid, true);
+        inferior.attach_thread(pid)?;
+        // The other threads of the process have to be attached one by one
+        let tasks = std::fs::read_dir(format!("/proc/{}/task", pid)).map_err(|_| DebuggerError::NoProcess)?;
+        for entry in tasks {
+            let tid = entry
+                .ok()
+                .and_then(|entry| entry.file_name().to_str()?.parse::<i32>().ok())
+                .map(Pid::from_raw);
+            if let Some(tid) = tid.filter(|tid| *tid != pid) {
+                // A thread that exited in the meantime is no loss
+                if inferior.attach_thread(tid).is_ok() {
+                    inferior.threads.push(Thread::new(inferior.next_thread_id, tid));
+                    inferior.next_thread_id += 1;
+                }
+            }
+        }
+        inferior.trace_children()?;
+        inferior.insert_breakpoints(break_list);
+        Ok(inferior)
+    }
+
+    /// Attaches to a single thread and waits until it is stopped.
+    fn attach_thread(&self, tid: Pid) -> Result<(), DebuggerError> {
+        ptrace::attach(tid)?;
+        // PTRACE_ATTACH sends a SIGSTOP, but another signal may be reported first
+        loop {
+            match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                WaitStatus::Stopped(_tid, signal::Signal::SIGSTOP) => return Ok(()),
+                WaitStatus::Stopped(_tid, signal) => ptrace::cont(tid, signal)?,
+                _ => return Err(DebuggerError::NoProcess),
+            }
+        }
+    }
+
+    fn traced(pid: Pid, attached: bool) -> Inferior {
+        Inferior {
+            pid,
+            tid: pid,
+            threads: vec![Thread::new(1, pid)],
+            next_thread_id: 2,
+            early_threads: Vec::new(),
+            attached,
+            unwinder: RefCell::new(Unwinder::new(pid)),
+            fork_policy: ForkPolicy::default(),
+            signals: SignalTable::default(),
+            program: PathBuf::new(),
+            other_program: false,
+            events: Vec::new(),
+        }
+    }
+
+    /// Asks the kernel to stop the process when it creates a thread, forks or execs, and records
+    /// which program it runs. New threads and forked children are traced from their first
+    /// instruction and inherit this.
+    fn trace_children(&mut self) -> Result<(), DebuggerError> {
+        for thread in &self.threads {
+            ptrace::setoptions(
+                thread.tid,
+                ptrace::Options::PTRACE_O_TRACECLONE
+                    | ptrace::Options::PTRACE_O_TRACEFORK
+                    | ptrace::Options::PTRACE_O_TRACEVFORK
+                    | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+                    | ptrace::Options::PTRACE_O_TRACEEXEC,
+            )?;
+        }
+        self.program = self.exe_path();
+        Ok(())
+    }
+
+    /// Returns the threads of the process, in the order they were created.
+    pub fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    /// Returns the current thread.
+    pub fn current_thread(&self) -> &Thread {
+        self.threads.iter().find(|thread| thread.tid == self.tid).unwrap()
+    }
+
+    /// Makes thread `id` the current one. Returns false if there is no such thread.
+    pub fn select_thread(&mut self, id: usize) -> bool {
+        match self.threads.iter().find(|thread| thread.id == id) {
+            Some(thread) => {
+                self.tid = thread.tid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn thread_mut(&mut self, tid: Pid) -> Option<&mut Thread> {
+        self.threads.iter_mut().find(|thread| thread.tid == tid)
+    }
+
+    /// Returns the path of the program the process is running.
+    pub fn exe_path(&self) -> PathBuf {
+        std::fs::read_link(format!("/proc/{}/exe", self.pid)).unwrap_or_default()
+    }
+
+    /// Sets how forks of the process are handled from now on.
+    pub fn set_fork_policy(&mut self, fork_policy: ForkPolicy) {
+        self.fork_policy = fork_policy;
+    }
+
+    /// Sets how signals the process receives are handled from now on.
+    pub fn set_signal_table(&mut self, signals: &SignalTable) {
+        self.signals = signals.clone();
+    }
+
+    /// Sets the signal the current thread receives when it resumes, or None to resume it without
+    /// one.
+    pub fn set_pending_signal(&mut self, signal: Option<signal::Signal>) {
+        let tid = self.tid;
+        self.thread_mut(tid).unwrap().pending_signal = signal;
+    }
+
+    /// Returns true if the process exec'd a program other than the one being debugged. Its
+    /// memory then has nothing to do with the breakpoints, which must be left out of it.
+    pub fn runs_other_program(&self) -> bool {
+        self.other_program
+    }
+
+    /// Returns the forks and execs that happened since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<ProcessEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Writes a 0xcc at every address in break_list, recording the original bytes. Addresses
+    /// that can't be written are dropped from break_list.
+    fn insert_breakpoints(&mut self, break_list: &mut HashMap<usize, u8>) {
         let bps = break_list.clone();
         for (addr, _) in bps {
-            match inferior.write_byte(addr, 0xcc) {
+            match self.write_byte(addr, 0xcc) {
                 Ok(inst) => {
                     break_list.insert(addr, inst);
                 },
-                Err(_) => println!("Invalid breakpoint {:#x}", addr),
+                Err(_) => {
+                    println!("Invalid breakpoint {:#x}", addr);
+                    break_list.remove(&addr);
+                }
             }
         }
+    }
+
+    /// Restores the original bytes of every breakpoint, clears the hardware watchpoints and lets
+    /// the process run on without deet.
+    pub fn detach(&mut self, break_list: &HashMap<usize, u8>) -> Result<(), DebuggerError> {
+        for (addr, orig_byte) in break_list {
+            self.write_byte(*addr, *orig_byte)?;
+        }
+        for slot in 0..NUM_WATCHPOINT_SLOTS {
+            self.set_hw_watchpoint(slot, None)?;
+        }
+        for thread in &self.threads {
+            ptrace::detach(thread.tid, thread.pending_signal)?;
+        }
+        // A SIGSTOP deet sent that hasn't arrived yet would stop the process again
+        if self.threads.iter().any(|thread| thread.stop_pending) {
+            signal::kill(self.pid, signal::Signal::SIGCONT)?;
+        }
+        Ok(())
+    }
 
-        Some(inferior)
+    /// Returns true if deet attached to this process instead of starting it.
+    pub fn is_attached(&self) -> bool {
+        self.attached
     }
 
-    pub fn continue_run(&mut self, break_list: &HashMap<usize, u8>) -> Result<Status, nix::Error> {
-        let mut regs = ptrace::getregs(self.pid())?;
-        let rip = regs.rip as usize;
+    /// Resumes the inferior until it hits a breakpoint, receives a signal or exits. If the inferior
+    /// stopped on a breakpoint, %rip is rewound to the breakpoint address.
+    ///
+    /// `stop_at` is called whenever the inferior traps on a breakpoint or watchpoint, and decides
+    /// whether to stop there or to keep going (e.g. because a breakpoint condition is false).
+    pub fn continue_run(
+        &mut self,
+        break_list: &HashMap<usize, u8>,
+        stop_at: &mut dyn FnMut(&Inferior, Trap) -> bool,
+    ) -> Result<Status, DebuggerError> {
+        loop {
+            if break_list.contains_key(&self.get_rip()?) {
+                match self.step_instruction(break_list)? {
+                    Status::Stopped(signal::Signal::SIGTRAP, rip) => {
+                        if self.check_watchpoints(stop_at)? {
+                            return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+                        }
+                    }
+                    other => return Ok(other),
+                }
+            }
 
-        if let Some(orig_inst) = break_list.get(&(rip - 1)) {
-            println!("Stopped at a breakpoint");
-            self.write_byte(rip - 1, *orig_inst).unwrap();
-            regs.rip = (rip - 1) as u64;
-            ptrace::setregs(self.pid(), regs).unwrap();
-            ptrace::step(self.pid(), None).unwrap();
+            match self.resume_and_wait(Request::Continue, break_list)? {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) if break_list.contains_key(&(rip - 1)) => {
+                    let mut regs = ptrace::getregs(self.tid)?;
+                    regs.rip = (rip - 1) as u64;
+                    ptrace::setregs(self.tid, regs)?;
+                    if stop_at(self, Trap::Breakpoint(rip - 1)) {
+                        return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip - 1));
+                    }
+                }
+                Status::Stopped(signal::Signal::SIGTRAP, rip) => {
+                    let slots = self.take_triggered_watchpoints()?;
+                    if slots.is_empty() || stop_at(self, Trap::Watchpoint(slots)) {
+                        return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+                    }
+                }
+                other => return Ok(other),
+            }
+        }
+    }
 
-            match self.wait(None).unwrap() {
-                Status::Exited(exit_code) => return Ok(Status::Exited(exit_code)),
-                Status::Signaled(signal)  => return Ok(Status::Signaled(signal)),
-                Status::Stopped(_, _) => {
-                    self.write_byte(rip - 1, 0xcc).unwrap();
-                } 
+    /// Executes a single instruction. If %rip sits on a breakpoint, the original instruction is
+    /// executed and the 0xcc is put back afterwards.
+    pub fn step_instruction(&mut self, break_list: &HashMap<usize, u8>) -> Result<Status, DebuggerError> {
+        let rip = self.get_rip()?;
+        match break_list.get(&rip) {
+            Some(orig_byte) => {
+                self.write_byte(rip, *orig_byte)?;
+                let status = self.resume_and_wait(Request::Step, break_list)?;
+                if let (Status::Stopped(_, _), false) = (&status, self.other_program) {
+                    self.write_byte(rip, 0xcc)?;
+                }
+                Ok(status)
             }
+            None => self.resume_and_wait(Request::Step, break_list),
         }
+    }
 
-        ptrace::cont(self.pid(), None)?;
-        self.wait(None)
+    /// Runs until the inferior reaches `addr` with a stack pointer of at least `min_rsp`, which
+    /// skips over recursive calls that pass through the same address. Stops early if a
+    /// breakpoint is hit or the inferior receives a signal.
+    fn run_until(
+        &mut self,
+        addr: usize,
+        min_rsp: usize,
+        break_list: &HashMap<usize, u8>,
+        stop_at: &mut dyn FnMut(&Inferior, Trap) -> bool,
+    ) -> Result<Status, DebuggerError> {
+        // Other threads may pass through `addr` too, but only the current one is waited for
+        let tid = self.tid;
+        let mut temp_list = break_list.clone();
+        let inserted = !break_list.contains_key(&addr);
+        if inserted {
+            temp_list.insert(addr, self.write_byte(addr, 0xcc)?);
+        }
+        let status = loop {
+            match self.continue_run(&temp_list, &mut |inferior, trap| match trap {
+                Trap::Breakpoint(bp_addr) if bp_addr == addr => true,
+                trap => stop_at(inferior, trap),
+            })? {
+                Status::Stopped(signal::Signal::SIGTRAP, rip)
+                    if rip == addr
+                        && (self.tid != tid
+                            || (ptrace::getregs(self.tid)?.rsp as usize) < min_rsp) => {}
+                other => break other,
+            }
+        };
+        if let (Status::Stopped(_, _), true, false) = (&status, inserted, self.other_program) {
+            self.write_byte(addr, temp_list[&addr])?;
+        }
+        if inserted {
+            // Children forked meanwhile got a copy of the temporary breakpoint
+            for event in self.events.iter_mut() {
+                if let ProcessEvent::Forked(child) | ProcessEvent::FollowFork(child) = event {
+                    child.write_byte(addr, temp_list[&addr])?;
+                }
+            }
+        }
+        Ok(status)
+    }
+
+    /// Called after single-stepping the instruction at `prev_rip`, when %rsp was `prev_rsp`.
+    /// Returns the return address if that instruction was a call.
+    fn called_from(&self, prev_rip: usize, prev_rsp: usize) -> Result<Option<usize>, DebuggerError> {
+        // A call pushes the address of the next instruction onto the stack
+        let rsp = ptrace::getregs(self.tid)?.rsp as usize;
+        if rsp != prev_rsp - 8 {
+            return Ok(None);
+        }
+        let ret_addr = self.read_word(rsp)?;
+        Ok(Some(ret_addr).filter(|ret_addr| *ret_addr > prev_rip && *ret_addr <= prev_rip + 16))
+    }
+
+    /// Executes `count` instructions. When `step_over_calls` is true, a call instruction runs
+    /// until the callee returns, and counts as a single instruction.
+    pub fn step_instructions(
+        &mut self,
+        count: usize,
+        step_over_calls: bool,
+        break_list: &HashMap<usize, u8>,
+        stop_at: &mut dyn FnMut(&Inferior, Trap) -> bool,
+    ) -> Result<Status, DebuggerError> {
+        let mut rip = self.get_rip()?;
+        for _ in 0..count {
+            let prev_rip = rip;
+            let prev_rsp = ptrace::getregs(self.tid)?.rsp as usize;
+            match self.step_instruction(break_list)? {
+                Status::Stopped(signal::Signal::SIGTRAP, new_rip) => rip = new_rip,
+                other => return Ok(other),
+            }
+            if self.check_watchpoints(stop_at)? {
+                break;
+            }
+            if !step_over_calls {
+                continue;
+            }
+            if let Some(ret_addr) = self.called_from(prev_rip, prev_rsp)? {
+                match self.run_until(ret_addr, prev_rsp, break_list, stop_at)? {
+                    Status::Stopped(signal::Signal::SIGTRAP, new_rip) if new_rip == ret_addr => {
+                        rip = new_rip
+                    }
+                    other => return Ok(other),
+                }
+            }
+        }
+        Ok(Status::Stopped(signal::Signal::SIGTRAP, rip))
+    }
+
+    /// Steps to the beginning of the next source line. When `step_into` is false, calls are
+    /// stepped over; otherwise execution stops at the first line of a called function that has
+    /// debugging information.
+    pub fn step_line(
+        &mut self,
+        debug_data: &DwarfData,
+        break_list: &HashMap<usize, u8>,
+        step_into: bool,
+        stop_at: &mut dyn FnMut(&Inferior, Trap) -> bool,
+    ) -> Result<Status, DebuggerError> {
+        let line_at = |addr| debug_data.get_line_from_addr(addr).map(|line| (line.file, line.number));
+        let mut rip = self.get_rip()?;
+        let mut curr_line = line_at(rip);
+        loop {
+            let prev_rip = rip;
+            let prev_rsp = ptrace::getregs(self.tid)?.rsp as usize;
+            match self.step_instruction(break_list)? {
+                Status::Stopped(signal::Signal::SIGTRAP, new_rip) => rip = new_rip,
+                other => return Ok(other),
+            }
+            if self.check_watchpoints(stop_at)? {
+                return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+            }
+
+            if let Some(ret_addr) = self.called_from(prev_rip, prev_rsp)? {
+                if let (true, Some(callee)) = (step_into, debug_data.get_function_for_addr(rip)) {
+                    let body_addr = debug_data.get_addr_after_prologue(callee);
+                    if body_addr == rip {
+                        return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+                    }
+                    return self.run_until(body_addr, 0, break_list, stop_at);
+                }
+                match self.run_until(ret_addr, prev_rsp, break_list, stop_at)? {
+                    Status::Stopped(signal::Signal::SIGTRAP, new_rip) if new_rip == ret_addr => {
+                        rip = new_rip
+                    }
+                    other => return Ok(other),
+                }
+            }
+
+            if debug_data.get_function_for_addr(rip).is_none() {
+                // We returned from the outermost function into code without debugging
+                // information, so there is no further line to stop at
+                return self.continue_run(break_list, stop_at);
+            }
+            let line = line_at(rip);
+            if line != curr_line {
+                if debug_data.is_line_start(rip) {
+                    return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+                }
+                // We landed in the middle of a line (e.g. after returning to the caller), so keep
+                // going until the start of the next one
+                curr_line = line;
+            }
+        }
+    }
+
+    /// Runs until the function of frame `level` returns to its caller.
+    pub fn finish(
+        &mut self,
+        level: usize,
+        break_list: &HashMap<usize, u8>,
+        stop_at: &mut dyn FnMut(&Inferior, Trap) -> bool,
+    ) -> Result<Status, DebuggerError> {
+        let frames = self.unwind_frames(level + 2)?;
+        match (frames.get(level).and_then(|frame| frame.cfa), frames.get(level + 1)) {
+            (Some(cfa), Some(caller)) => self.run_until(caller.pc, cfa, break_list, stop_at),
+            // Without unwind information we can't tell where the function returns to
+            _ => Err(DebuggerError::NoUnwindInfo),
+        }
+    }
+
+    /// Programs debug register `slot` to trap after any write to the `len` bytes at `addr`, or
+    /// disables the slot if `watch` is None. `len` must be 1, 2, 4 or 8 and `addr` aligned to it.
+    /// Debug registers belong to threads, so every thread is programmed.
+    pub fn set_hw_watchpoint(
+        &mut self,
+        slot: usize,
+        watch: Option<(usize, usize)>,
+    ) -> Result<(), DebuggerError> {
+        for thread in &self.threads {
+            set_thread_watchpoint(thread.tid, slot, watch)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the debug register slots whose watchpoints triggered since the last call, and
+    /// resets the status register.
+    fn take_triggered_watchpoints(&mut self) -> Result<Vec<usize>, DebuggerError> {
+        let dr6 = ptrace::read_user(self.tid, debug_register_offset(6))? as u64;
+        let slots: Vec<usize> = (0..NUM_WATCHPOINT_SLOTS)
+            .filter(|slot| dr6 & (1 << slot) != 0)
+            .collect();
+        if !slots.is_empty() {
+            unsafe {
+                ptrace::write_user(self.tid, debug_register_offset(6), std::ptr::null_mut())?;
+            }
+        }
+        Ok(slots)
+    }
+
+    /// After a SIGTRAP, asks `stop_at` whether to stop if a watchpoint triggered.
+    fn check_watchpoints(
+        &mut self,
+        stop_at: &mut dyn FnMut(&Inferior, Trap) -> bool,
+    ) -> Result<bool, DebuggerError> {
+        let slots = self.take_triggered_watchpoints()?;
+        Ok(!slots.is_empty() && stop_at(self, Trap::Watchpoint(slots)))
     }
 
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
     }
 
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
     /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, DebuggerError> {
+        loop {
+            return Ok(match waitpid(self.pid(), options)? {
+                WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
+                WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
+                // A ptrace event stops the process just like a signal
+                WaitStatus::Stopped(_pid, signal) | WaitStatus::PtraceEvent(_pid, signal, _) => {
+                    let regs = ptrace::getregs(self.tid)?;
+                    Status::Stopped(signal, regs.rip as usize)
+                }
+                // The process resumed after a SIGCONT; it hasn't stopped yet
+                WaitStatus::Continued(_pid) => continue,
+                other => return Err(DebuggerError::UnexpectedStatus(format!("{:?}", other))),
+            });
+        }
+    }
+
+    /// Resumes the process as `request` says and waits for it to stop. Stepping runs only the
+    /// current thread, while continuing runs them all. As soon as one thread stops, the others
+    /// are stopped too, and the one that stopped becomes the current thread.
+    ///
+    /// Thread creation, forks and execs on the way are handled and recorded for `take_events`.
+    /// They are passed over, except for a fork whose child the debugger follows and an exec,
+    /// which are reported as a SIGTRAP stop so that the debugger can take over.
+    fn resume_and_wait(
+        &mut self,
+        request: Request,
+        break_list: &HashMap<usize, u8>,
+    ) -> Result<Status, DebuggerError> {
+        let pid = self.pid;
+        self.resume_threads(request)?;
+        loop {
+            let status = waitpid(Pid::from_raw(-1), Some(WaitPidFlag::__WALL))?;
+            let tid = match status.pid() {
+                Some(tid) => tid,
+                None => continue,
+            };
+            let thread = match self.threads.iter_mut().find(|thread| thread.tid == tid) {
+                Some(thread) => thread,
+                None => {
+                    // A new thread can report its first stop before its creator reports the clone
+                    if let WaitStatus::Stopped(_tid, signal::Signal::SIGSTOP) = status {
+                        self.early_threads.push(tid);
+                    }
+                    continue;
+                }
+            };
+            thread.running = false;
+            match status {
+                // The main thread is only reported once all the others are gone
+                WaitStatus::Exited(_pid, exit_code) if tid == pid => {
+                    return Ok(Status::Exited(exit_code))
+                }
+                WaitStatus::Signaled(_pid, signal, _core_dumped) if tid == pid => {
+                    return Ok(Status::Signaled(signal))
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    let id = thread.id;
+                    self.threads.retain(|thread| thread.tid != tid);
+                    self.events.push(ProcessEvent::ThreadExited(id, tid));
+                    if tid == self.tid {
+                        // The thread being stepped is gone, so let the others run on
+                        self.tid = self.threads[0].tid;
+                        self.resume_threads(Request::Continue)?;
+                    }
+                }
+                WaitStatus::Stopped(_tid, signal::Signal::SIGSTOP) if thread.stop_pending => {
+                    thread.stop_pending = false;
+                    if let Request::Continue = request {
+                        ptrace::cont(tid, None)?;
+                        thread.running = true;
+                    }
+                }
+                // SIGTRAP is deet's own: breakpoints, steps and watchpoints trap with it
+                WaitStatus::Stopped(_tid, signal) if signal != signal::Signal::SIGTRAP => {
+                    let policy = self.signals.get(signal);
+                    if policy.pass {
+                        thread.pending_signal = Some(signal);
+                    }
+                    if policy.stop {
+                        self.tid = tid;
+                        self.stop_other_threads(break_list)?;
+                        return Ok(Status::Stopped(signal, self.get_rip()?));
+                    }
+                    self.resume_threads(request)?;
+                }
+                WaitStatus::Stopped(_tid, signal) => {
+                    self.tid = tid;
+                    self.stop_other_threads(break_list)?;
+                    return Ok(Status::Stopped(signal, self.get_rip()?));
+                }
+                WaitStatus::PtraceEvent(_pid, _signal, libc::PTRACE_EVENT_CLONE) => {
+                    self.add_thread(tid)?;
+                    self.resume_threads(request)?;
+                }
+                WaitStatus::PtraceEvent(_pid, _signal, event) => {
+                    let curr_tid = std::mem::replace(&mut self.tid, tid);
+                    if self.handle_event(event, break_list)? {
+                        self.stop_other_threads(break_list)?;
+                        return Ok(Status::Stopped(signal::Signal::SIGTRAP, self.get_rip()?));
+                    }
+                    // An exec leaves only the main thread
+                    if self.thread_mut(curr_tid).is_some() {
+                        self.tid = curr_tid;
+                    }
+                    self.resume_threads(request)?;
+                }
+                _ => {}
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+        }
     }
 
-    pub fn kill(&mut self) {
-        self.child.kill().expect("Killing child fail!");
-        self.wait(None).expect("Waiting child fail!");
+    /// Resumes the stopped threads: only the current one when stepping, every one when
+    /// continuing.
+    fn resume_threads(&mut self, request: Request) -> Result<(), DebuggerError> {
+        for thread in self.threads.iter_mut().filter(|thread| !thread.running) {
+            match request {
+                Request::Step if thread.tid == self.tid => {
+                    ptrace::step(thread.tid, thread.pending_signal.take())?
+                }
+                Request::Step => continue,
+                Request::Continue => ptrace::cont(thread.tid, thread.pending_signal.take())?,
+            }
+            thread.running = true;
+        }
+        Ok(())
     }
 
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let mut rip = ptrace::getregs(self.pid()).unwrap().rip as usize;
-        let mut rbp = ptrace::getregs(self.pid()).unwrap().rbp as usize;
-        // println!("%rip : {:#x}, %rbp : {:#x}", rip, rbp);
-        loop {
-            let line = debug_data.get_line_from_addr(rip).unwrap();
-            let func = debug_data.get_function_from_addr(rip).unwrap();
-            println!("{} {}", func, line);
-            if &func == "main" {
-                break;
+    /// Stops every thread that is still running. Stops they report instead of the SIGSTOP are
+    /// kept for later: a thread that hit a breakpoint is backed up to hit it again when it
+    /// resumes, and signals are delivered then.
+    fn stop_other_threads(&mut self, break_list: &HashMap<usize, u8>) -> Result<(), DebuggerError> {
+        let running: Vec<Pid> = self
+            .threads
+            .iter()
+            .filter(|thread| thread.running)
+            .map(|thread| thread.tid)
+            .collect();
+        let pid = self.pid;
+        for tid in running {
+            let thread = self.threads.iter_mut().find(|thread| thread.tid == tid).unwrap();
+            if !thread.stop_pending {
+                let res = unsafe {
+                    libc::syscall(libc::SYS_tgkill, pid.as_raw(), tid.as_raw(), libc::SIGSTOP)
+                };
+                nix::errno::Errno::result(res)?;
+                thread.stop_pending = true;
+            }
+            thread.running = false;
+            match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                WaitStatus::Stopped(_tid, signal::Signal::SIGSTOP) => thread.stop_pending = false,
+                WaitStatus::Stopped(_tid, signal::Signal::SIGTRAP) => {
+                    let mut regs = ptrace::getregs(tid)?;
+                    if break_list.contains_key(&(regs.rip as usize - 1)) {
+                        regs.rip -= 1;
+                        ptrace::setregs(tid, regs)?;
+                    }
+                }
+                WaitStatus::Stopped(_tid, signal) if self.signals.get(signal).pass => {
+                    thread.pending_signal = Some(signal)
+                }
+                WaitStatus::Stopped(..) => {}
+                WaitStatus::PtraceEvent(_pid, _signal, libc::PTRACE_EVENT_CLONE) => {
+                    self.add_thread(tid)?;
+                }
+                WaitStatus::PtraceEvent(_pid, _signal, event) => {
+                    let curr_tid = std::mem::replace(&mut self.tid, tid);
+                    self.handle_event(event, break_list)?;
+                    self.tid = curr_tid;
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    let id = thread.id;
+                    self.threads.retain(|thread| thread.tid != tid);
+                    self.events.push(ProcessEvent::ThreadExited(id, tid));
+                }
+                _ => {}
             }
-            rip = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType)? as usize;
-            rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType)? as usize;
         }
         Ok(())
     }
 
-    pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+    /// Starts tracking the thread that thread `creator` just created. The new thread starts out
+    /// stopped.
+    fn add_thread(&mut self, creator: Pid) -> Result<(), DebuggerError> {
+        let tid = Pid::from_raw(ptrace::getevent(creator)? as i32);
+        match self.early_threads.iter().position(|early| *early == tid) {
+            Some(index) => {
+                self.early_threads.remove(index);
+            }
+            None => {
+                waitpid(tid, Some(WaitPidFlag::__WALL))?;
+            }
+        }
+        copy_watchpoints(creator, tid)?;
+        let id = self.next_thread_id;
+        self.next_thread_id += 1;
+        self.threads.push(Thread::new(id, tid));
+        self.events.push(ProcessEvent::NewThread(id, tid));
+        Ok(())
+    }
+
+    /// Handles a PTRACE_EVENT stop of the current thread. Returns true if the debugger has to
+    /// take over.
+    fn handle_event(
+        &mut self,
+        event: i32,
+        break_list: &HashMap<usize, u8>,
+    ) -> Result<bool, DebuggerError> {
+        match event {
+            libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK => {
+                let child_pid = Pid::from_raw(ptrace::getevent(self.tid)? as i32);
+                // The child starts out stopped by a SIGSTOP
+                waitpid(child_pid, Some(WaitPidFlag::__WALL))?;
+                let mut child = Inferior::traced(child_pid, false);
+                child.fork_policy = self.fork_policy;
+                child.program = self.program.clone();
+                child.other_program = self.other_program;
+                if self.fork_policy.follow_child {
+                    self.events.push(ProcessEvent::FollowFork(child));
+                    return Ok(true);
+                }
+                // The parent of a vfork sleeps until the child execs or exits, so the child can't
+                // be kept stopped. It also shares the parent's memory, so its breakpoints are
+                // taken out of both until then.
+                if self.fork_policy.detach || event == libc::PTRACE_EVENT_VFORK {
+                    child.detach(break_list)?;
+                    self.events.push(ProcessEvent::DetachedFork(child_pid));
+                } else {
+                    self.events.push(ProcessEvent::Forked(child));
+                }
+                Ok(false)
+            }
+            libc::PTRACE_EVENT_VFORK_DONE => {
+                for addr in break_list.keys() {
+                    self.write_byte(*addr, 0xcc)?;
+                }
+                Ok(false)
+            }
+            libc::PTRACE_EVENT_EXEC => {
+                // The old program is gone, and with it the breakpoints, the mapped files and every
+                // thread but one, which takes over the thread id of the process
+                *self.unwinder.borrow_mut() = Unwinder::new(self.pid());
+                let pid = self.pid;
+                self.threads.retain(|thread| thread.tid == pid);
+                self.tid = pid;
+                let exe = self.exe_path();
+                self.other_program = exe != self.program;
+                if !self.other_program {
+                    for addr in break_list.keys() {
+                        self.write_byte(*addr, 0xcc)?;
+                    }
+                }
+                self.events.push(ProcessEvent::Exec(exe.display().to_string()));
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    pub fn kill(&mut self) -> Result<(), DebuggerError> {
+        signal::kill(self.pid, signal::Signal::SIGKILL)?;
+        // Every thread has to be reaped; the main thread comes last
+        for thread in self.threads.iter().filter(|thread| thread.tid != self.pid) {
+            let _ = waitpid(thread.tid, Some(WaitPidFlag::__WALL));
+        }
+        self.wait(None)?;
+        Ok(())
+    }
+
+    /// Unwinds the stack using the call frame information, innermost frame first. The walk ends
+    /// at the outermost frame, at a frame without CFI, or after MAX_FRAMES frames.
+    pub fn frames(&self) -> Result<Vec<Frame>, DebuggerError> {
+        self.unwind_frames(MAX_FRAMES)
+    }
+
+    /// Returns the innermost frame, without unwinding the rest of the stack.
+    pub fn current_frame(&self) -> Result<Frame, DebuggerError> {
+        self.unwind_frames(1)?.pop().ok_or(DebuggerError::NoUnwindInfo)
+    }
+
+    fn unwind_frames(&self, max_frames: usize) -> Result<Vec<Frame>, DebuggerError> {
+        let mut regs = Registers::from_user_regs(&ptrace::getregs(self.tid)?);
+        let mut unwinder = self.unwinder.borrow_mut();
+        let mut frames: Vec<Frame> = Vec::new();
+        while frames.len() < max_frames {
+            let pc = match regs.pc() {
+                Some(pc) if pc != 0 => pc,
+                _ => break,
+            };
+            let unwound = unwinder.unwind(&regs, !frames.is_empty());
+            let prev_cfa = frames.last().and_then(|frame| frame.cfa);
+            frames.push(Frame {
+                level: frames.len(),
+                inline_depth: 0,
+                pc,
+                cfa: unwound.as_ref().map(|(cfa, _)| *cfa),
+                regs,
+            });
+            match unwound {
+                // The stack grows down, so callers must have higher CFAs; anything else means
+                // the stack is corrupt
+                Some((cfa, caller)) if prev_cfa.is_none_or(|prev_cfa| cfa > prev_cfa) => {
+                    regs = caller
+                }
+                _ => break,
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Returns the name of the ELF symbol containing `addr`, for code without debugging
+    /// information.
+    pub fn symbol_for_addr(&self, addr: usize) -> Option<String> {
+        self.unwinder.borrow_mut().symbol_for_addr(addr)
+    }
+
+    /// Returns the current instruction pointer of the inferior.
+    pub fn get_rip(&self) -> Result<usize, DebuggerError> {
+        Ok(ptrace::getregs(self.tid)?.rip as usize)
+    }
+
+    pub fn get_registers(&self) -> Result<libc::user_regs_struct, DebuggerError> {
+        Ok(ptrace::getregs(self.tid)?)
+    }
+
+    /// Reads the x87 and SSE registers, which nix has no wrapper for.
+    pub fn get_fp_registers(&self) -> Result<libc::user_fpregs_struct, DebuggerError> {
+        let mut fpregs = std::mem::MaybeUninit::<libc::user_fpregs_struct>::uninit();
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                self.tid.as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                fpregs.as_mut_ptr(),
+            )
+        };
+        nix::errno::Errno::result(res)?;
+        // The kernel filled in the struct, since the call succeeded
+        Ok(unsafe { fpregs.assume_init() })
+    }
+
+    /// Sets register `name` (see `register_field`).
+    pub fn set_register(&mut self, name: &str, value: u64) -> Result<(), DebuggerError> {
+        let mut regs = ptrace::getregs(self.tid)?;
+        *register_field(&mut regs, name).ok_or_else(|| DebuggerError::UnknownRegister(name.to_string()))? = value;
+        Ok(ptrace::setregs(self.tid, regs)?)
+    }
+
+    fn read_word(&self, addr: usize) -> Result<usize, DebuggerError> {
+        Ok(ptrace::read(self.tid, addr as ptrace::AddressType)? as usize)
+    }
+
+    /// Reads `len` bytes of the inferior's memory, starting at `addr`.
+    pub fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, DebuggerError> {
+        // Memory can't extend past the end of the address space
+        if addr.checked_add(len).and_then(|end| end.checked_add(size_of::<usize>())).is_none() {
+            return Err(DebuggerError::Ptrace(nix::Error::EFAULT));
+        }
+        // A single process_vm_readv is much cheaper than a ptrace call per word, but it can't
+        // read pages the process itself can't read, so fall back to ptrace if it comes up short.
+        if len > 4 * size_of::<usize>() {
+            let mut bytes = vec![0; len];
+            let remote = [RemoteIoVec { base: addr, len }];
+            if let Ok(read) = process_vm_readv(self.pid(), &mut [IoSliceMut::new(&mut bytes)], &remote) {
+                if read == len {
+                    return Ok(bytes);
+                }
+            }
+        }
         let aligned_addr = align_addr_to_word(addr);
+        let mut bytes = Vec::with_capacity(len + size_of::<usize>() * 2);
+        let mut curr_addr = aligned_addr;
+        while curr_addr < addr + len {
+            let word = ptrace::read(self.tid, curr_addr as ptrace::AddressType)? as u64;
+            bytes.extend_from_slice(&word.to_le_bytes());
+            curr_addr += size_of::<usize>();
+        }
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
-        unsafe {
-            ptrace::write(
-                self.pid(),
-                aligned_addr as ptrace::AddressType,
-                updated_word as *mut std::ffi::c_void,
-            )?;
+        Ok(bytes[byte_offset..byte_offset + len].to_vec())
+    }
+
+    pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, DebuggerError> {
+        Ok(self.write_bytes(addr, &[val])?[0])
+    }
+
+    /// Writes `bytes` to the inferior's memory at `addr` and returns the bytes that were there
+    /// before. ptrace writes whole words, so each word is read first and only the requested bytes
+    /// in it are changed.
+    pub fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<Vec<u8>, DebuggerError> {
+        let end = addr + bytes.len();
+        let mut orig_bytes = Vec::with_capacity(bytes.len());
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < end {
+            let word = ptrace::read(self.tid, word_addr as ptrace::AddressType)? as u64;
+            let mut word_bytes = word.to_le_bytes();
+            for (byte_addr, byte) in (word_addr..).zip(word_bytes.iter_mut()) {
+                if (addr..end).contains(&byte_addr) {
+                    orig_bytes.push(*byte);
+                    *byte = bytes[byte_addr - addr];
+                }
+            }
+            unsafe {
+                ptrace::write(
+                    self.tid,
+                    word_addr as ptrace::AddressType,
+                    u64::from_le_bytes(word_bytes) as *mut std::ffi::c_void,
+                )?;
+            }
+            word_addr += size_of::<usize>();
         }
-        Ok(orig_byte as u8)
+        Ok(orig_bytes)
     } 
 }
@@ -0,0 +1,240 @@
+//! Evaluation of the DWARF expressions that say where a variable is stored. Optimized code keeps
+//! variables in registers, splits them into pieces and drops them for parts of a function, so
+//! where a variable is depends on the pc and the registers of the frame it is read in.
+
+use crate::dwarf_data::Location;
+use crate::examine::unit_value;
+use crate::inferior::Inferior;
+use crate::unwind::Frame;
+use gimli::{EndianSlice, EvaluationResult, LittleEndian, Piece};
+use std::mem::size_of;
+
+/// DWARF register numbers of %xmm0 to %xmm15 on x86-64
+const XMM_REGISTERS: std::ops::RangeInclusive<u16> = 17..=32;
+
+type Pieces<'a> = Vec<Piece<EndianSlice<'a, LittleEndian>>>;
+
+/// Where the value of a variable can be found in a frame.
+pub enum Place {
+    Memory(usize),
+    /// The value isn't in memory as a whole; these are its bytes, gathered from registers,
+    /// pieces or constants
+    Value(Vec<u8>),
+    OptimizedOut,
+}
+
+/// Finds a `size`-byte variable at `location`. `frame_base` is the DW_AT_frame_base of the
+/// variable's function. Only globals at fixed addresses and constants can be found without a
+/// frame.
+pub fn locate(
+    location: &Location,
+    size: usize,
+    frame_base: Option<&Location>,
+    frame: Option<&Frame>,
+    inferior: Option<&Inferior>,
+) -> Result<Place, String> {
+    Locator {
+        frame,
+        inferior,
+        frame_base,
+    }
+    .locate(location, size)
+}
+
+#[derive(Clone, Copy)]
+struct Locator<'a> {
+    frame: Option<&'a Frame>,
+    inferior: Option<&'a Inferior>,
+    frame_base: Option<&'a Location>,
+}
+
+impl Locator<'_> {
+    fn locate(&self, location: &Location, size: usize) -> Result<Place, String> {
+        match location {
+            Location::Address(addr) => Ok(Place::Memory(*addr)),
+            Location::FramePointerOffset(offset) => {
+                Ok(Place::Memory((self.frame_base()? as isize + offset) as usize))
+            }
+            Location::Expression(bytes, encoding) => match self.evaluate(bytes, *encoding)? {
+                Some(pieces) => self.assemble(&pieces, size),
+                None => Ok(Place::OptimizedOut),
+            },
+            Location::List(entries) => {
+                let pc = self.frame()?.lookup_addr();
+                match entries.iter().find(|(start, end, _)| *start <= pc && pc < *end) {
+                    Some((_, _, location)) => self.locate(location, size),
+                    None => Ok(Place::OptimizedOut),
+                }
+            }
+            Location::Constant(bytes) => {
+                let mut bytes = bytes.clone();
+                bytes.resize(size, 0);
+                Ok(Place::Value(bytes))
+            }
+            Location::OptimizedOut => Ok(Place::OptimizedOut),
+        }
+    }
+
+    fn frame(&self) -> Result<&Frame, String> {
+        self.frame.ok_or_else(|| "No frame selected.".to_string())
+    }
+
+    fn read(&self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+        self.inferior
+            .ok_or("The program is not being run.")?
+            .read_bytes(addr as usize, len)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))
+    }
+
+    /// Value of a register in the frame, or None if the frame doesn't preserve it. Only the low
+    /// 64 bits of vector registers are read.
+    fn register(&self, register: gimli::Register) -> Result<Option<u64>, String> {
+        let frame = self.frame()?;
+        if frame.regs.tracks(register) {
+            return Ok(frame.regs.get(register));
+        }
+        if !XMM_REGISTERS.contains(&register.0) {
+            return Err(format!("Cannot read DWARF register {}.", register.0));
+        }
+        // No vector register survives a call, so only the innermost frame has them
+        if frame.level > 0 {
+            return Ok(None);
+        }
+        let fpregs = self
+            .inferior
+            .ok_or("The program has no registers now.")?
+            .get_fp_registers()
+            .map_err(|err| err.to_string())?;
+        let index = 4 * (register.0 - XMM_REGISTERS.start()) as usize;
+        Ok(Some(
+            fpregs.xmm_space[index] as u64 | (fpregs.xmm_space[index + 1] as u64) << 32,
+        ))
+    }
+
+    fn cfa(&self) -> Result<u64, String> {
+        // Without CFI, assume a standard frame, where the CFA lies just above the saved %rbp
+        // and the return address
+        let frame = self.frame()?;
+        frame
+            .cfa
+            .or_else(|| frame.regs.rbp().map(|rbp| rbp + 16))
+            .map(|cfa| cfa as u64)
+            .ok_or_else(|| "Cannot find the frame's call frame address.".to_string())
+    }
+
+    /// Evaluates the function's frame base. gcc uses DW_OP_call_frame_cfa, which is also what is
+    /// assumed if the function doesn't say.
+    fn frame_base(&self) -> Result<u64, String> {
+        let frame_base = match self.frame_base {
+            Some(frame_base) => frame_base,
+            None => return self.cfa(),
+        };
+        // The frame base can't refer to itself
+        let locator = Locator {
+            frame_base: None,
+            ..*self
+        };
+        match locator.locate(frame_base, size_of::<u64>())? {
+            Place::Memory(addr) => Ok(addr as u64),
+            // A register location like DW_OP_reg6 means the register holds the frame base
+            Place::Value(bytes) => Ok(unit_value(&bytes)),
+            Place::OptimizedOut => Err("Cannot find the frame base.".to_string()),
+        }
+    }
+
+    /// Runs a DWARF expression, supplying the registers and memory it asks for. Returns None if
+    /// it depends on something the frame no longer has.
+    fn evaluate<'a>(
+        &self,
+        bytes: &'a [u8],
+        encoding: gimli::Encoding,
+    ) -> Result<Option<Pieces<'a>>, String> {
+        let error = |err: gimli::Error| format!("Cannot evaluate the variable's location: {}", err);
+        let expression = gimli::Expression(EndianSlice::new(bytes, LittleEndian));
+        let mut evaluation = expression.evaluation(encoding);
+        let mut result = evaluation.evaluate().map_err(error)?;
+        loop {
+            result = match result {
+                EvaluationResult::Complete => return Ok(Some(evaluation.result())),
+                EvaluationResult::RequiresMemory { address, size, .. } => {
+                    let value = unit_value(&self.read(address, size as usize)?);
+                    evaluation.resume_with_memory(gimli::Value::Generic(value))
+                }
+                EvaluationResult::RequiresRegister { register, .. } => {
+                    match self.register(register)? {
+                        Some(value) => evaluation.resume_with_register(gimli::Value::Generic(value)),
+                        None => return Ok(None),
+                    }
+                }
+                EvaluationResult::RequiresFrameBase => {
+                    evaluation.resume_with_frame_base(self.frame_base()?)
+                }
+                EvaluationResult::RequiresCallFrameCfa => {
+                    evaluation.resume_with_call_frame_cfa(self.cfa()?)
+                }
+                // Addresses are used as they are, since the programs deet debugs aren't
+                // position-independent
+                EvaluationResult::RequiresRelocatedAddress(address) => {
+                    evaluation.resume_with_relocated_address(address)
+                }
+                EvaluationResult::RequiresBaseType(_) => {
+                    evaluation.resume_with_base_type(gimli::ValueType::Generic)
+                }
+                // DW_OP_entry_value needs registers as they were when the function was called,
+                // which are gone by now
+                EvaluationResult::RequiresEntryValue(_) => return Ok(None),
+                _ => return Err("Unsupported DWARF location expression.".to_string()),
+            }
+            .map_err(error)?;
+        }
+    }
+
+    /// Turns the result of an expression into a place. A single memory location is the common
+    /// case; anything else is read piece by piece into a value.
+    fn assemble(
+        &self,
+        pieces: &[Piece<EndianSlice<LittleEndian>>],
+        size: usize,
+    ) -> Result<Place, String> {
+        if let [Piece {
+            size_in_bits: None,
+            location: gimli::Location::Address { address },
+            ..
+        }] = pieces
+        {
+            return Ok(Place::Memory(*address as usize));
+        }
+        let mut value = Vec::with_capacity(size);
+        for piece in pieces {
+            let len = match piece.size_in_bits {
+                Some(bits) => (bits as usize).div_ceil(8),
+                None => size,
+            };
+            let mut bytes = match &piece.location {
+                gimli::Location::Empty => return Ok(Place::OptimizedOut),
+                gimli::Location::Register { register } => match self.register(*register)? {
+                    Some(value) => value.to_le_bytes().to_vec(),
+                    None => return Ok(Place::OptimizedOut),
+                },
+                gimli::Location::Address { address } => self.read(*address, len)?,
+                gimli::Location::Value { value } => match value {
+                    gimli::Value::F32(value) => value.to_le_bytes().to_vec(),
+                    gimli::Value::F64(value) => value.to_le_bytes().to_vec(),
+                    value => value
+                        .to_u64(!0)
+                        .map_err(|err| format!("Cannot evaluate the variable's location: {}", err))?
+                        .to_le_bytes()
+                        .to_vec(),
+                },
+                gimli::Location::Bytes { value } => value.to_vec(),
+                gimli::Location::ImplicitPointer { .. } => {
+                    return Err("Cannot access a pointer the compiler optimized out.".to_string())
+                }
+            };
+            bytes.resize(len, 0);
+            value.extend(bytes);
+        }
+        value.resize(size, 0);
+        Ok(Place::Value(value))
+    }
+}
@@ -1,24 +1,77 @@
+mod breakpoint;
 mod debugger;
 mod debugger_command;
+mod disassemble;
 mod inferior;
 mod dwarf_data;
+mod error;
+mod examine;
+mod expression;
 mod gimli_wrapper;
+mod location;
+mod signals;
+mod unwind;
 
 use crate::debugger::Debugger;
 use nix::sys::signal::{signal, SigHandler, Signal};
+use nix::unistd::Pid;
 use std::env;
 
+fn usage(program: &str) -> ! {
+    println!("Usage: {} [--batch] [-x <script>]... <target program>", program);
+    println!("       {} [--batch] [-x <script>]... --pid <pid> [target program]", program);
+    std::process::exit(1);
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
+    let mut scripts = Vec::new();
+    let mut batch = false;
+    let mut pid = None;
+    let mut targets = Vec::new();
+    let mut options = args[1..].iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--batch" => batch = true,
+            "-x" => match options.next() {
+                Some(script) => scripts.push(script.clone()),
+                None => usage(&args[0]),
+            },
+            "--pid" => match options.next().and_then(|pid| pid.parse::<i32>().ok()) {
+                Some(value) => pid = Some(Pid::from_raw(value)),
+                None => usage(&args[0]),
+            },
+            option if option.starts_with('-') => usage(&args[0]),
+            target => targets.push(target.to_string()),
+        }
     }
-    let target = &args[1];
+    let target = match (targets.as_slice(), pid) {
+        ([target], _) => target.clone(),
+        // Without a target program, read the symbols from the binary the process is running
+        ([], Some(pid)) => format!("/proc/{}/exe", pid),
+        _ => usage(&args[0]),
+    };
     // println!("{:?}", args);
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let mut debugger = match Debugger::new(&target) {
+        Ok(debugger) => debugger,
+        Err(err) => {
+            println!("{}: {}", target, err);
+            std::process::exit(1);
+        }
+    };
+    for script in &scripts {
+        if let Err(err) = debugger.queue_script(script) {
+            println!("{}: {}", script, err);
+            std::process::exit(1);
+        }
+    }
+    debugger.set_batch(batch);
+    if let Some(pid) = pid {
+        debugger.attach(pid);
+    }
+    std::process::exit(debugger.run());
 }
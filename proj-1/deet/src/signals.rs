@@ -0,0 +1,187 @@
+//! What deet does with the signals the inferior receives, as set with `handle`.
+
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+
+/// How one signal is handled.
+#[derive(Clone, Copy)]
+pub struct SignalPolicy {
+    /// Whether the inferior stops and deet reports the signal; otherwise the inferior is resumed
+    /// right away
+    pub stop: bool,
+    /// Whether the signal is delivered to the inferior when it resumes
+    pub pass: bool,
+}
+
+#[derive(Clone)]
+pub struct SignalTable {
+    policies: HashMap<Signal, SignalPolicy>,
+}
+
+impl Default for SignalTable {
+    /// The same defaults as gdb: signals that are part of normal operation, such as SIGCHLD and
+    /// SIGALRM, are passed on without stopping, and SIGINT (ctrl+c) and SIGTRAP belong to the
+    /// debugger.
+    fn default() -> Self {
+        let policies = Signal::iterator()
+            .map(|signal| {
+                let policy = match signal {
+                    Signal::SIGINT | Signal::SIGTRAP => SignalPolicy {
+                        stop: true,
+                        pass: false,
+                    },
+                    // Passing on a SIGSTOP would stop the process again as soon as it resumes
+                    Signal::SIGSTOP => SignalPolicy {
+                        stop: true,
+                        pass: false,
+                    },
+                    Signal::SIGALRM
+                    | Signal::SIGURG
+                    | Signal::SIGCHLD
+                    | Signal::SIGWINCH
+                    | Signal::SIGIO
+                    | Signal::SIGVTALRM
+                    | Signal::SIGPROF => SignalPolicy {
+                        stop: false,
+                        pass: true,
+                    },
+                    _ => SignalPolicy {
+                        stop: true,
+                        pass: true,
+                    },
+                };
+                (signal, policy)
+            })
+            .collect();
+        SignalTable { policies }
+    }
+}
+
+impl SignalTable {
+    pub fn get(&self, signal: Signal) -> SignalPolicy {
+        self.policies[&signal]
+    }
+
+    pub fn get_mut(&mut self, signal: Signal) -> &mut SignalPolicy {
+        self.policies.get_mut(&signal).unwrap()
+    }
+
+    /// Returns every signal with its policy, in signal number order.
+    pub fn iter(&self) -> impl Iterator<Item = (Signal, SignalPolicy)> + '_ {
+        Signal::iterator().map(move |signal| (signal, self.policies[&signal]))
+    }
+}
+
+/// Parses a signal given as a name such as "SIGUSR1" or "usr1", or as a number.
+pub fn parse_signal(name: &str) -> Result<Signal, String> {
+    if let Ok(number) = name.parse::<i32>() {
+        return Signal::try_from(number)
+            .map_err(|_| "Only signals 1-31 are valid as numeric signals.".to_string());
+    }
+    let upper = name.to_ascii_uppercase();
+    let upper = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+    upper.parse().map_err(|_| format!("Unknown signal name \"{}\".", name))
+}
+
+/// Returns the description of a signal, e.g. "User defined signal 1".
+pub fn describe_signal(signal: Signal) -> String {
+    // strsignal returns a pointer to a static or thread-local string
+    unsafe { CStr::from_ptr(libc::strsignal(signal as i32)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Whether `signal` means the program crashed: its default action is to dump core. SIGTRAP is
+/// left out, since it is how breakpoints and steps stop the inferior.
+pub fn is_crash_signal(signal: Signal) -> bool {
+    matches!(
+        signal,
+        Signal::SIGQUIT
+            | Signal::SIGILL
+            | Signal::SIGABRT
+            | Signal::SIGBUS
+            | Signal::SIGFPE
+            | Signal::SIGSEGV
+            | Signal::SIGSYS
+            | Signal::SIGXCPU
+            | Signal::SIGXFSZ
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_signal_names() {
+        assert_eq!(parse_signal("SIGUSR1"), Ok(Signal::SIGUSR1));
+        assert_eq!(parse_signal("usr1"), Ok(Signal::SIGUSR1));
+        assert_eq!(parse_signal("sigsegv"), Ok(Signal::SIGSEGV));
+        assert_eq!(parse_signal("Int"), Ok(Signal::SIGINT));
+    }
+
+    #[test]
+    fn test_parse_signal_numbers() {
+        assert_eq!(parse_signal("2"), Ok(Signal::SIGINT));
+        assert_eq!(parse_signal("11"), Ok(Signal::SIGSEGV));
+        assert_eq!(parse_signal("31"), Ok(Signal::SIGSYS));
+    }
+
+    #[test]
+    fn test_parse_signal_errors() {
+        assert_eq!(parse_signal("SIGFOO"), Err("Unknown signal name \"SIGFOO\".".to_string()));
+        assert_eq!(parse_signal("foo"), Err("Unknown signal name \"foo\".".to_string()));
+        let out_of_range = Err("Only signals 1-31 are valid as numeric signals.".to_string());
+        assert_eq!(parse_signal("0"), out_of_range);
+        assert_eq!(parse_signal("32"), out_of_range);
+        assert_eq!(parse_signal("-1"), out_of_range);
+    }
+
+    #[test]
+    fn test_default_policies() {
+        let table = SignalTable::default();
+        let policy = |signal| {
+            let policy = table.get(signal);
+            (policy.stop, policy.pass)
+        };
+        assert_eq!(policy(Signal::SIGINT), (true, false));
+        assert_eq!(policy(Signal::SIGTRAP), (true, false));
+        assert_eq!(policy(Signal::SIGSTOP), (true, false));
+        assert_eq!(policy(Signal::SIGALRM), (false, true));
+        assert_eq!(policy(Signal::SIGCHLD), (false, true));
+        assert_eq!(policy(Signal::SIGWINCH), (false, true));
+        assert_eq!(policy(Signal::SIGSEGV), (true, true));
+        assert_eq!(policy(Signal::SIGUSR1), (true, true));
+    }
+
+    #[test]
+    fn test_table_covers_every_signal_in_order() {
+        let table = SignalTable::default();
+        let numbers: Vec<i32> = table.iter().map(|(signal, _)| signal as i32).collect();
+        let mut sorted = numbers.clone();
+        sorted.sort_unstable();
+        assert_eq!(numbers, sorted);
+        assert_eq!(numbers.len(), Signal::iterator().count());
+    }
+
+    #[test]
+    fn test_get_mut_changes_policy() {
+        let mut table = SignalTable::default();
+        table.get_mut(Signal::SIGUSR1).stop = false;
+        assert!(!table.get(Signal::SIGUSR1).stop);
+        assert!(table.get(Signal::SIGUSR1).pass);
+        // Other signals are left alone
+        assert!(table.get(Signal::SIGUSR2).stop);
+    }
+
+    #[test]
+    fn test_crash_signals() {
+        assert!(is_crash_signal(Signal::SIGSEGV));
+        assert!(is_crash_signal(Signal::SIGABRT));
+        assert!(!is_crash_signal(Signal::SIGTRAP));
+        assert!(!is_crash_signal(Signal::SIGINT));
+        assert!(!is_crash_signal(Signal::SIGKILL));
+    }
+}
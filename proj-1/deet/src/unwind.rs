@@ -0,0 +1,297 @@
+//! Stack unwinding using the call frame information (CFI) that compilers emit in `.eh_frame` and
+//! `.debug_frame`. Unlike following the chain of saved %rbp values, this works for code built
+//! without frame pointers and for the shared libraries loaded into the inferior.
+
+use gimli::{BaseAddresses, CfaRule, EndianRcSlice, RegisterRule, RunTimeEndian, UnwindContext};
+use gimli::{UnwindSection, UnwindTableRow};
+use nix::sys::ptrace;
+use nix::unistd::Pid;
+use object::{Object, ObjectSection, ObjectSegment, ObjectSymbol, SymbolKind};
+use std::fs;
+use std::rc::Rc;
+
+type Reader = EndianRcSlice<RunTimeEndian>;
+
+/// DWARF register numbers of %rbp and %rsp on x86-64
+const RBP: usize = 6;
+const RSP: usize = 7;
+/// DWARF register number of the return address column, which holds %rip
+const RETURN_ADDRESS: usize = 16;
+const NUM_REGISTERS: usize = 17;
+
+/// Register values of a frame, indexed by DWARF register number. None means the value can't be
+/// recovered in this frame.
+#[derive(Clone)]
+pub struct Registers([Option<u64>; NUM_REGISTERS]);
+
+impl Registers {
+    pub fn from_user_regs(regs: &libc::user_regs_struct) -> Registers {
+        let values = [
+            regs.rax, regs.rdx, regs.rcx, regs.rbx, regs.rsi, regs.rdi, regs.rbp, regs.rsp,
+            regs.r8, regs.r9, regs.r10, regs.r11, regs.r12, regs.r13, regs.r14, regs.r15, regs.rip,
+        ];
+        Registers(values.map(Some))
+    }
+
+    pub fn pc(&self) -> Option<usize> {
+        self.0[RETURN_ADDRESS].map(|pc| pc as usize)
+    }
+
+    pub fn rbp(&self) -> Option<usize> {
+        self.0[RBP].map(|rbp| rbp as usize)
+    }
+
+    pub fn get(&self, register: gimli::Register) -> Option<u64> {
+        self.0.get(register.0 as usize).copied().flatten()
+    }
+
+    /// Whether `register` is one of the general purpose registers kept here, rather than a
+    /// floating-point or vector register
+    pub fn tracks(&self, register: gimli::Register) -> bool {
+        (register.0 as usize) < NUM_REGISTERS
+    }
+}
+
+/// One frame of the inferior's call stack.
+#[derive(Clone)]
+pub struct Frame {
+    /// Number of the frame, counting from 0 for the innermost one
+    pub level: usize,
+    /// Functions inlined into a frame share its registers, and are shown as frames of their own
+    /// with the same level. This counts the inlined calls between the innermost function at
+    /// `pc` and the function this frame stands for.
+    pub inline_depth: usize,
+    /// Instruction the frame is executing. In caller frames, this is the return address.
+    pub pc: usize,
+    /// Canonical frame address: the value of %rsp before the call that created this frame. None
+    /// if there is no CFI for `pc`.
+    pub cfa: Option<usize>,
+    /// Register values as they are in this frame
+    pub regs: Registers,
+}
+
+impl Frame {
+    /// Address to look up the frame's function, line and variables with. In caller frames this
+    /// is the call instruction rather than the return address, which may already be on the next
+    /// line or even in the next function.
+    pub fn lookup_addr(&self) -> usize {
+        if self.level == 0 {
+            self.pc
+        } else {
+            self.pc - 1
+        }
+    }
+}
+
+/// The unwind information and symbols of one executable file mapped into the inferior.
+struct UnwindTable {
+    path: String,
+    /// Runtime addresses of the file's executable mapping
+    start: usize,
+    end: usize,
+    /// Runtime address minus the address in the file, which is nonzero for shared libraries and
+    /// position-independent executables
+    bias: usize,
+    eh_frame: Option<(gimli::EhFrame<Reader>, BaseAddresses)>,
+    debug_frame: Option<gimli::DebugFrame<Reader>>,
+    /// (start, end, name) of the function symbols, as file addresses
+    symbols: Vec<(usize, usize, String)>,
+}
+
+impl UnwindTable {
+    fn load(path: &str, start: usize, end: usize, offset: u64) -> Option<UnwindTable> {
+        let file = fs::File::open(path).ok()?;
+        let mmap = unsafe { memmap2::Mmap::map(&file).ok()? };
+        let object = object::File::parse(&*mmap).ok()?;
+        let endian = if object.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+        // The segment that was mapped tells us where the file expected to be loaded
+        let segment = object.segments().find(|segment| {
+            let (file_start, file_len) = segment.file_range();
+            file_start <= offset && offset < file_start + file_len
+        })?;
+        let file_addr = segment.address() + (offset - segment.file_range().0);
+        let bias = (start as u64).wrapping_sub(file_addr) as usize;
+
+        let section = |name| {
+            let section = object.section_by_name(name)?;
+            let data = section.uncompressed_data().ok()?;
+            Some((section.address(), EndianRcSlice::new(Rc::from(&*data), endian)))
+        };
+        let eh_frame = section(".eh_frame").map(|(address, data)| {
+            let mut eh_frame = gimli::EhFrame::from(data);
+            eh_frame.set_address_size(8);
+            let mut bases = BaseAddresses::default().set_eh_frame(address);
+            if let Some(text) = object.section_by_name(".text") {
+                bases = bases.set_text(text.address());
+            }
+            (eh_frame, bases)
+        });
+        let debug_frame = section(".debug_frame").map(|(_, data)| {
+            let mut debug_frame = gimli::DebugFrame::from(data);
+            debug_frame.set_address_size(8);
+            debug_frame
+        });
+
+        let mut symbols: Vec<(usize, usize, String)> = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.size() > 0)
+            .filter_map(|symbol| {
+                let start = symbol.address() as usize;
+                Some((start, start + symbol.size() as usize, symbol.name().ok()?.to_string()))
+            })
+            .collect();
+        symbols.sort();
+
+        Some(UnwindTable {
+            path: path.to_string(),
+            start,
+            end,
+            bias,
+            eh_frame,
+            debug_frame,
+            symbols,
+        })
+    }
+
+    /// Looks up the CFI row for `addr`, preferring .eh_frame over .debug_frame.
+    fn find_row(&self, addr: usize, ctx: &mut UnwindContext<Reader>) -> Option<UnwindTableRow<Reader>> {
+        let addr = addr.wrapping_sub(self.bias) as u64;
+        if let Some((eh_frame, bases)) = &self.eh_frame {
+            if let Some(row) = find_row_in(eh_frame, bases, addr, ctx) {
+                return Some(row);
+            }
+        }
+        let debug_frame = self.debug_frame.as_ref()?;
+        find_row_in(debug_frame, &BaseAddresses::default(), addr, ctx)
+    }
+}
+
+fn find_row_in<S: UnwindSection<Reader>>(
+    section: &S,
+    bases: &BaseAddresses,
+    addr: u64,
+    ctx: &mut UnwindContext<Reader>,
+) -> Option<UnwindTableRow<Reader>> {
+    section
+        .unwind_info_for_address(bases, ctx, addr, S::cie_from_offset)
+        .ok()
+        .cloned()
+}
+
+/// Unwinds the stack of a process. Unwind tables are loaded lazily from the files in
+/// /proc/<pid>/maps, so libraries loaded after the process started are picked up as well.
+pub struct Unwinder {
+    pid: Pid,
+    tables: Vec<UnwindTable>,
+    ctx: Box<UnwindContext<Reader>>,
+}
+
+impl Unwinder {
+    pub fn new(pid: Pid) -> Unwinder {
+        Unwinder {
+            pid,
+            tables: Vec::new(),
+            ctx: Box::new(UnwindContext::new()),
+        }
+    }
+
+    /// Finds the mapped file containing `addr`, reading the process's mappings again if the
+    /// address isn't in any file loaded so far.
+    fn table_for_addr(&mut self, addr: usize) -> Option<usize> {
+        let find = |tables: &[UnwindTable]| {
+            tables
+                .iter()
+                .position(|table| table.start <= addr && addr < table.end)
+        };
+        if let Some(index) = find(&self.tables) {
+            return Some(index);
+        }
+        self.load_mappings();
+        find(&self.tables)
+    }
+
+    fn load_mappings(&mut self) {
+        let maps = match fs::read_to_string(format!("/proc/{}/maps", self.pid)) {
+            Ok(maps) => maps,
+            Err(_) => return,
+        };
+        for line in maps.lines() {
+            // Each line looks like "start-end perms offset dev inode path"
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 || !fields[1].contains('x') || !fields[5].starts_with('/') {
+                continue;
+            }
+            let path = fields[5..].join(" ");
+            let range = fields[0]
+                .split_once('-')
+                .and_then(|(start, end)| {
+                    Some((usize::from_str_radix(start, 16).ok()?, usize::from_str_radix(end, 16).ok()?))
+                });
+            let (start, end, offset) = match (range, u64::from_str_radix(fields[2], 16)) {
+                (Some((start, end)), Ok(offset)) => (start, end, offset),
+                _ => continue,
+            };
+            if self.tables.iter().any(|table| table.start == start && table.path == path) {
+                continue;
+            }
+            if let Some(table) = UnwindTable::load(&path, start, end, offset) {
+                self.tables.push(table);
+            }
+        }
+    }
+
+    /// Computes the CFA of the frame with registers `regs`, along with the registers of its
+    /// caller. `is_caller` says whether the frame's pc is a return address. Returns None if
+    /// there is no CFI for the frame.
+    pub fn unwind(&mut self, regs: &Registers, is_caller: bool) -> Option<(usize, Registers)> {
+        // A return address is the instruction after the call, which may already belong to the
+        // next function if the call never returns
+        let addr = regs.pc()? - is_caller as usize;
+        let index = self.table_for_addr(addr)?;
+        let row = self.tables[index].find_row(addr, &mut self.ctx)?;
+
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                regs.get(*register)?.wrapping_add(*offset as u64)
+            }
+            CfaRule::Expression(_) => return None,
+        };
+        // Registers the CFI doesn't mention are assumed to be preserved
+        let mut caller = regs.clone();
+        caller.0[RSP] = Some(cfa);
+        for number in 0..NUM_REGISTERS {
+            caller.0[number] = match row.register(gimli::Register(number as u16)) {
+                RegisterRule::Undefined if number == RETURN_ADDRESS => None,
+                RegisterRule::Undefined | RegisterRule::SameValue => continue,
+                RegisterRule::Offset(offset) => {
+                    let addr = cfa.wrapping_add(offset as u64);
+                    ptrace::read(self.pid, addr as ptrace::AddressType)
+                        .ok()
+                        .map(|word| word as u64)
+                }
+                RegisterRule::ValOffset(offset) => Some(cfa.wrapping_add(offset as u64)),
+                RegisterRule::Register(register) => regs.get(register),
+                _ => None,
+            };
+        }
+        Some((cfa as usize, caller))
+    }
+
+    /// Returns the name of the ELF symbol containing `addr`, for code without debugging
+    /// information.
+    pub fn symbol_for_addr(&mut self, addr: usize) -> Option<String> {
+        let index = self.table_for_addr(addr)?;
+        let table = &self.tables[index];
+        let addr = addr.wrapping_sub(table.bias);
+        table
+            .symbols
+            .iter()
+            .find(|(start, end, _)| *start <= addr && addr < *end)
+            .map(|(_, _, name)| name.clone())
+    }
+}